@@ -1,11 +1,21 @@
 #![allow(non_snake_case, mixed_script_confusables)]
-#![feature(non_ascii_idents)]
 
 use blind_sig_bench::{
     abe::Abe,
-    common::FourMoveBlindSig,
+    blind_bls::BlindBls,
+    blind_rsa::BlindRsaRandomized,
+    clause_schnorr::ClauseBlindSchnorr,
+    common::BlindSigProtocol,
+    export::{self, ClientTotals, ResultRow, RunMetadata},
+    latency::Network,
+    results::Estimates,
+    retry::{ClientStats, RetryPolicy},
+    scenario::{Scenario, Scheme, ServerBackend},
     schnorr::BlindSchnorr,
-    webserver::{make_client, start_server},
+    seed::{stream_rng, SeededDistr, Stream},
+    session::{AdmissionPolicy, SessionStore},
+    transport::{start_channel_server, HttpTransport, Transport},
+    webserver::{make_client, start_backend},
 };
 
 use std::{
@@ -14,104 +24,253 @@ use std::{
     time::Duration,
 };
 
-use criterion::{criterion_group, criterion_main, Criterion};
-use dashmap::DashMap;
-use rand_distr::Distribution;
-
-const SERVER_ADDR: &str = "localhost:14147";
-
-// Number of threads we give to the server
-const THREADPOOL_SIZES: &[usize] = &[1, 4, 16];
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, Criterion,
+};
+use rand_distr::Normal;
 
-// Number of clients that connect to the server within a benchmark. Each client waits some
-// interarrival time after the previous client before connecting
-const NUM_CLIENTS: usize = 100;
+// The schemes, pool sizes, workloads and latency are all described by a scenario. Set
+// BENCH_SCENARIO to the path of a scenario file to run something other than scenarios/ec2.toml.
 
-// Average time between clients connecting. This is modeled as a Poisson point process, and so the
-// time between arrivals is an exponential distribution with λ = 1 / mean_interarrival_time.
-const INTERARRIVAL_TIMES: &[f64] = &[1f64, 10f64, 50f64, 90f64, 130f64];
+// If this environment variable is set, the clients talk to an in-process server over channels
+// instead of over HTTP. This takes the TCP stack out of the measurements. The in-process server is
+// always a thread pool, so this can't be combined with the async backend.
+const IN_MEMORY_VAR: &str = "BENCH_IN_MEMORY";
 
-// 30ms mean latency between server and client (this is roughly what I get on a WiFi network
-// between NYC and msu.edu ). Let's say this is normally distributed with standard deviation of 5ms
-// so that 95% of connections have latency between 20ms and 40ms.
-const LATENCY_MEAN: f64 = 30f64;
-const LATENCY_STD: f64 = 5f64;
+// Once everything has run, a row for each benchmark goes to results.csv and results.json in
+// Criterion's output directory. Set BENCH_RESULTS to a path (minus the extension) to put them
+// somewhere else.
 
-fn bench_scheme<S: FourMoveBlindSig>(
+fn bench_scheme<S: BlindSigProtocol + 'static>(
     bencher: &mut Criterion,
-    group_name: &str,
+    scenario: &Scenario,
+    scheme: Scheme,
     server_thread_pool_size: usize,
+    metadata: &RunMetadata,
+    rows: &mut Vec<ResultRow>,
 ) {
-    let mut group = bencher.benchmark_group(group_name);
+    let group_name = scenario.group_name(scheme);
+    let mut group = bencher.benchmark_group(&group_name);
     //group.measurement_time(std::time::Duration::from_secs(300));
 
-    let mut csprng = rand::thread_rng();
+    // The server sleeps after each response, or the clients' transports delay each message,
+    // depending on the latency model. Each benchmark reseeds the sleeps through its own copy.
+    let latency_distr = SeededDistr::new(
+        scenario
+            .latency
+            .server_distr()
+            .expect("invalid latency model"),
+        stream_rng(scenario.seed, "", Stream::ServerLatency),
+    );
+    let network = scenario
+        .latency
+        .network()
+        .expect("couldn't load network model");
 
-    // Network latency is a normal distribution if you squint
-    let latency_distr = rand_distr::Normal::new(LATENCY_MEAN, LATENCY_STD).unwrap();
+    // Thread-safe global state for the server. Clients that find the server full get a 409 and
+    // retry later.
+    let my_global_state: Arc<SessionStore<<S as BlindSigProtocol>::ServerSession>> = Arc::new(
+        SessionStore::new(scenario.session_timeout(), AdmissionPolicy::Reject),
+    );
 
-    // Thread-safe global state for the server
-    let my_global_state: Arc<DashMap<String, <S as FourMoveBlindSig>::ServerState>> =
-        Arc::new(DashMap::new());
+    let in_memory = std::env::var_os(IN_MEMORY_VAR).is_some();
+    let totals = if in_memory {
+        // Start the server. It stops once the transport is dropped.
+        let (_privkey, pubkey, transport) = start_channel_server::<S, _>(
+            server_thread_pool_size,
+            my_global_state,
+            latency_distr.clone(),
+        );
+        bench_clients::<S, _>(
+            &mut group,
+            scenario,
+            server_thread_pool_size,
+            &pubkey,
+            &network,
+            &latency_distr,
+            transport,
+        )
+    } else {
+        // Start the server. Setting stop_var to true will kill it.
+        let (_privkey, pubkey, stop_var) = start_backend::<S, _>(
+            scenario.server_backend,
+            &scenario.server_addr,
+            server_thread_pool_size,
+            my_global_state,
+            latency_distr.clone(),
+        );
+        let transport = HttpTransport::new(&scenario.server_addr);
+        let totals = bench_clients::<S, _>(
+            &mut group,
+            scenario,
+            server_thread_pool_size,
+            &pubkey,
+            &network,
+            &latency_distr,
+            transport,
+        );
 
-    // Start the server. Setting stop_var to true will kill it.
-    let (_privkey, pubkey, stop_var) = start_server::<S, _>(
-        SERVER_ADDR,
-        server_thread_pool_size,
-        my_global_state,
-        latency_distr,
-    );
+        // Tell the server to stop
+        stop_var.store(true, SeqCst);
+        // Wait a second for the server to get the message
+        sleep(Duration::from_secs(1));
 
-    for expected_iat in INTERARRIVAL_TIMES {
-        // Interarrival distribution of a Poisson point process with rate λ is the exponential
-        // distribution with parameter 1/λ
-        let client_arrival_distr = rand_distr::Exp::new(1f64 / expected_iat).unwrap();
+        totals
+    };
+    group.finish();
 
-        let bench_name = format!(
-            "{}-core server handling {} clients at {}ms EIAT",
-            server_thread_pool_size, NUM_CLIENTS, expected_iat
-        );
+    // Criterion has written its estimates by now. Pick them up for the results file.
+    for (expected_iat, client_totals) in totals {
+        let bench_name = scenario.bench_name(server_thread_pool_size, expected_iat);
+        let path = export::criterion_dir()
+            .join(&group_name)
+            .join(&bench_name)
+            .join("new")
+            .join("estimates.json");
+        match Estimates::read(&path) {
+            Ok(estimates) => rows.push(ResultRow::new(
+                metadata,
+                scenario,
+                scheme,
+                server_thread_pool_size,
+                expected_iat,
+                if in_memory { "in-memory" } else { "http" },
+                &estimates,
+                client_totals,
+            )),
+            Err(e) => eprintln!("couldn't read estimates for {}: {}", bench_name, e),
+        }
+    }
+}
 
-        // Bench how long it takes to spawn NUM_CLIENTS many clients, waiting expected_iat
-        // milliseconds between each other, connecting to a server which is running on
+fn bench_clients<S: BlindSigProtocol, T: Transport + Clone>(
+    group: &mut BenchmarkGroup<WallTime>,
+    scenario: &Scenario,
+    server_thread_pool_size: usize,
+    pubkey: &S::Pubkey,
+    network: &Network,
+    latency_distr: &SeededDistr<Normal<f64>>,
+    transport: T,
+) -> Vec<(f64, ClientTotals)> {
+    // The client totals of every benchmark that actually ran, i.e., wasn't filtered out
+    let mut totals = Vec::new();
+
+    for &expected_iat in &scenario.interarrival_times_ms {
+        let mean_interarrival = Duration::from_secs_f64(expected_iat / 1000.0);
+        let bench_name = scenario.bench_name(server_thread_pool_size, expected_iat);
+
+        // Bench how long it takes to spawn num_clients many clients, waiting expected_iat
+        // milliseconds between each other on average, connecting to a server which is running on
         // server_thread_pool_size many cores.
 
-        group.bench_function(bench_name, |b| {
+        // Keep track of how much time the clients spend backing off, across all iterations
+        let mut num_retries = 0;
+        let mut backoff_time = Duration::default();
+        let mut client_time = Duration::default();
+
+        group.bench_function(&bench_name, |b| {
             b.iter(|| {
+                // With a seed, the schedule, the delays and the server's sleeps are the same every
+                // iteration, every run, and for every scheme
+                let mut arrival_rng = stream_rng(scenario.seed, &bench_name, Stream::Arrivals);
+                let mut network_rng = stream_rng(scenario.seed, &bench_name, Stream::Network);
+                latency_distr.reseed(stream_rng(
+                    scenario.seed,
+                    &bench_name,
+                    Stream::ServerLatency,
+                ));
+
                 let mut threads = Vec::new();
-                for _ in 0..NUM_CLIENTS {
-                    let client = make_client::<S>(SERVER_ADDR, pubkey.clone());
+                for _ in 0..scenario.num_clients {
+                    let client = make_client::<S, _>(
+                        network.connect(&mut network_rng, transport.clone()),
+                        pubkey.clone(),
+                        RetryPolicy::default(),
+                    );
                     threads.push(std::thread::spawn(client));
 
-                    let pause_time =
-                        std::cmp::max(0, client_arrival_distr.sample(&mut csprng) as i64);
-                    sleep(Duration::from_millis(pause_time as u64));
+                    sleep(
+                        scenario
+                            .arrival_process
+                            .sample(&mut arrival_rng, mean_interarrival),
+                    );
                 }
 
                 for thread in threads.into_iter() {
-                    thread.join().unwrap();
+                    let stats: ClientStats = thread.join().unwrap().expect("client failed");
+                    num_retries += stats.num_retries as u64;
+                    backoff_time += stats.backoff_time;
+                    client_time += stats.total_time;
                 }
             })
         });
+
+        if !client_time.is_zero() {
+            let backoff_fraction = backoff_time.as_secs_f64() / client_time.as_secs_f64();
+            println!(
+                "{}: {} retries, {:.1}% of client time spent backing off",
+                bench_name,
+                num_retries,
+                100.0 * backoff_fraction
+            );
+            totals.push((
+                expected_iat,
+                ClientTotals {
+                    num_retries,
+                    backoff_fraction,
+                },
+            ));
+        }
     }
 
-    // Tell the server to stop
-    stop_var.store(true, SeqCst);
-    // Wait a second for the server to get the message
-    sleep(Duration::from_secs(1));
+    totals
 }
 
-fn bench_schnorr(bencher: &mut Criterion) {
-    // Schnorr is sequential so the threadpool size is always 1
-    bench_scheme::<BlindSchnorr>(bencher, "Sequential Blind Schnorr", 1);
-}
+fn bench_all(bencher: &mut Criterion) {
+    let scenario = Scenario::from_env().expect("couldn't load scenario");
+    if std::env::var_os(IN_MEMORY_VAR).is_some()
+        && scenario.server_backend != ServerBackend::ThreadPool
+    {
+        panic!("{} only works with the thread-pool backend", IN_MEMORY_VAR);
+    }
+    let metadata = RunMetadata::collect();
+    let mut rows = Vec::new();
+
+    for run in &scenario.schemes {
+        for &pool_size in &run.pool_sizes {
+            let scheme = run.scheme;
+            let rows = &mut rows;
+            match scheme {
+                Scheme::BlindSchnorr => bench_scheme::<BlindSchnorr>(
+                    bencher, &scenario, scheme, pool_size, &metadata, rows,
+                ),
+                Scheme::ClauseBlindSchnorr => bench_scheme::<ClauseBlindSchnorr>(
+                    bencher, &scenario, scheme, pool_size, &metadata, rows,
+                ),
+                Scheme::Abe => {
+                    bench_scheme::<Abe>(bencher, &scenario, scheme, pool_size, &metadata, rows)
+                }
+                Scheme::BlindRsa => bench_scheme::<BlindRsaRandomized>(
+                    bencher, &scenario, scheme, pool_size, &metadata, rows,
+                ),
+                Scheme::BlindBls => {
+                    bench_scheme::<BlindBls>(bencher, &scenario, scheme, pool_size, &metadata, rows)
+                }
+            }
+        }
+    }
 
-fn bench_abe(bencher: &mut Criterion) {
-    // Abe is parallel so we benchmark it for various threadpool sizes
-    for &thread_pool_size in THREADPOOL_SIZES {
-        bench_scheme::<Abe>(bencher, "Parallel Abe", thread_pool_size);
+    if !rows.is_empty() {
+        let (csv_path, json_path) =
+            export::write_results(export::results_path(), &rows).expect("couldn't write results");
+        println!(
+            "Wrote {} results to {} and {}",
+            rows.len(),
+            csv_path.display(),
+            json_path.display()
+        );
     }
 }
 
-criterion_group!(benches, bench_abe, bench_schnorr);
+criterion_group!(benches, bench_all);
 criterion_main!(benches);
@@ -0,0 +1,164 @@
+#![allow(non_snake_case, mixed_script_confusables)]
+
+use blind_sig_bench::{
+    abe::Abe, clause_schnorr::ClauseBlindSchnorr, common::FourMoveBlindSig, schnorr::BlindSchnorr,
+};
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use threadpool::ThreadPool;
+
+// Numbers of sessions that are run at once in a single step of the protocol
+const NUM_SESSIONS: &[usize] = &[1, 2, 4, 8, 16, 32];
+
+/// All the values produced by a single honest run of the protocol. We keep these around so that
+/// each step can be benched in isolation on the outputs of the steps before it.
+struct Transcript<S: FourMoveBlindSig> {
+    server_resp1: S::ServerResp1,
+    client_state: S::ClientState,
+    client_resp: S::ClientResp,
+    server_resp2: S::ServerResp2,
+}
+
+fn make_transcript<S: FourMoveBlindSig>(
+    privkey: &S::Privkey,
+    pubkey: &S::Pubkey,
+    m: &[u8],
+) -> Transcript<S> {
+    let mut csprng = rand::thread_rng();
+
+    let (server_state, server_resp1) = S::sign1(&mut csprng, pubkey);
+    let (client_state, client_resp) = S::user1(&mut csprng, pubkey, m, &server_resp1);
+    let server_resp2 = S::sign2(privkey, server_state, &client_resp);
+
+    Transcript {
+        server_resp1,
+        client_state,
+        client_resp,
+        server_resp2,
+    }
+}
+
+/// Runs `f` on every input using the given threadpool, and waits for all of them to finish
+fn run_sessions<T, F>(pool: &ThreadPool, inputs: Vec<T>, f: &Arc<F>)
+where
+    T: Send + 'static,
+    F: Fn(T) + Send + Sync + 'static,
+{
+    for input in inputs {
+        let f = f.clone();
+        pool.execute(move || f(input));
+    }
+    pool.join();
+}
+
+fn bench_scheme<S: FourMoveBlindSig>(bencher: &mut Criterion, group_name: &str) {
+    let mut group = bencher.benchmark_group(group_name);
+
+    let mut csprng = rand::thread_rng();
+    let m: &'static [u8] = b"Hello world";
+
+    group.bench_function("keygen", |b| b.iter(|| S::keygen(&mut csprng)));
+
+    let (privkey, pubkey) = S::keygen(&mut csprng);
+
+    for &num_sessions in NUM_SESSIONS {
+        // A scheme can only run as many sessions at once as it permits. Anything beyond that
+        // gets queued up in the threadpool.
+        let pool = ThreadPool::new(std::cmp::min(S::MAX_PARALLEL_SESSIONS, num_sessions));
+
+        // Do an honest run of each session up front. The steps below only ever clone out of
+        // these, so none of the earlier steps' work gets counted in the later steps' timings.
+        let transcripts: Vec<Transcript<S>> = (0..num_sessions)
+            .map(|_| make_transcript::<S>(&privkey, &pubkey, m))
+            .collect();
+
+        // Step 1: the server commits
+        let sign1 = Arc::new(move |()| {
+            black_box(S::sign1(&mut rand::thread_rng(), &pubkey));
+        });
+        group.bench_function(format!("step 1[s = {}]", num_sessions), |b| {
+            b.iter_batched(
+                || vec![(); num_sessions],
+                |inputs| run_sessions(&pool, inputs, &sign1),
+                BatchSize::SmallInput,
+            )
+        });
+
+        // Step 2: the client blinds the commitment and makes a challenge
+        let user1 = Arc::new(move |server_resp1: S::ServerResp1| {
+            black_box(S::user1(&mut rand::thread_rng(), &pubkey, m, &server_resp1));
+        });
+        group.bench_function(format!("step 2[s = {}]", num_sessions), |b| {
+            b.iter_batched(
+                || transcripts.iter().map(|t| t.server_resp1.clone()).collect(),
+                |inputs| run_sessions(&pool, inputs, &user1),
+                BatchSize::SmallInput,
+            )
+        });
+
+        // Step 3: the server responds to the challenge
+        let sign2 = Arc::new(
+            move |(server_state, client_resp): (S::ServerState, S::ClientResp)| {
+                black_box(S::sign2(&privkey, server_state, &client_resp));
+            },
+        );
+        group.bench_function(format!("step 3[s = {}]", num_sessions), |b| {
+            b.iter_batched(
+                // sign2 consumes the server's state, so every iteration needs fresh ones. Which
+                // nonces the server answers with doesn't change how long it takes.
+                || {
+                    transcripts
+                        .iter()
+                        .map(|t| {
+                            let (server_state, _) = S::sign1(&mut rand::thread_rng(), &pubkey);
+                            (server_state, t.client_resp.clone())
+                        })
+                        .collect()
+                },
+                |inputs| run_sessions(&pool, inputs, &sign2),
+                BatchSize::SmallInput,
+            )
+        });
+
+        // Step 4: the client checks the response and unblinds it into a signature
+        let user2 = Arc::new(
+            move |(client_state, server_resp2): (S::ClientState, S::ServerResp2)| {
+                black_box(S::user2(&pubkey, &client_state, m, &server_resp2).unwrap());
+            },
+        );
+        group.bench_function(format!("step 4[s = {}]", num_sessions), |b| {
+            b.iter_batched(
+                || {
+                    transcripts
+                        .iter()
+                        .map(|t| (t.client_state.clone(), t.server_resp2.clone()))
+                        .collect()
+                },
+                |inputs| run_sessions(&pool, inputs, &user2),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    // Verification is done by whoever receives the signature, so there's no notion of sessions
+    let t = make_transcript::<S>(&privkey, &pubkey, m);
+    let sig = S::user2(&pubkey, &t.client_state, m, &t.server_resp2).unwrap();
+    group.bench_function("verify", |b| b.iter(|| S::verify(&pubkey, m, &sig)));
+}
+
+fn bench_schnorr(bencher: &mut Criterion) {
+    bench_scheme::<BlindSchnorr>(bencher, "Sequential Blind Schnorr");
+}
+
+fn bench_abe(bencher: &mut Criterion) {
+    bench_scheme::<Abe>(bencher, "Parallel Abe");
+}
+
+fn bench_clause_schnorr(bencher: &mut Criterion) {
+    bench_scheme::<ClauseBlindSchnorr>(bencher, "Parallel Clause Blind Schnorr");
+}
+
+criterion_group!(benches, bench_abe, bench_schnorr, bench_clause_schnorr);
+criterion_main!(benches);
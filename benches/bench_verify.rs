@@ -0,0 +1,68 @@
+#![allow(non_snake_case, mixed_script_confusables)]
+
+use blind_sig_bench::{abe::Abe, common::FourMoveBlindSig, schnorr::BlindSchnorr};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+// Numbers of signatures that are verified at once
+const BATCH_SIZES: &[usize] = &[1, 4, 16, 64, 256];
+
+/// Makes `n` signatures on distinct messages under the given key
+fn make_sigs<S: FourMoveBlindSig>(
+    privkey: &S::Privkey,
+    pubkey: &S::Pubkey,
+    n: usize,
+) -> Vec<(Vec<u8>, S::Signature)> {
+    let mut csprng = rand::thread_rng();
+
+    (0..n)
+        .map(|i| {
+            let m = format!("Hello world {}", i).into_bytes();
+            let (server_state, server_resp1) = S::sign1(&mut csprng, pubkey);
+            let (client_state, client_resp) = S::user1(&mut csprng, pubkey, &m, &server_resp1);
+            let server_resp2 = S::sign2(privkey, server_state, &client_resp);
+            let sig = S::user2(pubkey, &client_state, &m, &server_resp2).unwrap();
+            (m, sig)
+        })
+        .collect()
+}
+
+/// Benches verifying `n` signatures one by one against verifying them as a batch. Throughput is
+/// in signatures, so the reported time per element is the per-signature cost.
+fn bench_scheme<S, F>(bencher: &mut Criterion, group_name: &str, verify_batch: F)
+where
+    S: FourMoveBlindSig,
+    F: Fn(&S::Pubkey, &[(&[u8], S::Signature)]) -> bool,
+{
+    let mut group = bencher.benchmark_group(group_name);
+    let (privkey, pubkey) = S::keygen(&mut rand::thread_rng());
+
+    for &n in BATCH_SIZES {
+        let sigs = make_sigs::<S>(&privkey, &pubkey, n);
+        let sigs: Vec<(&[u8], S::Signature)> = sigs
+            .iter()
+            .map(|(m, sig)| (m.as_slice(), sig.clone()))
+            .collect();
+        group.throughput(Throughput::Elements(n as u64));
+
+        group.bench_function(format!("single[n = {}]", n), |b| {
+            b.iter(|| assert!(sigs.iter().all(|(m, sig)| S::verify(&pubkey, m, sig))))
+        });
+        group.bench_function(format!("batch[n = {}]", n), |b| {
+            b.iter(|| assert!(verify_batch(&pubkey, &sigs)))
+        });
+    }
+}
+
+fn bench_schnorr(bencher: &mut Criterion) {
+    bench_scheme::<BlindSchnorr, _>(bencher, "Blind Schnorr verification", |pubkey, sigs| {
+        BlindSchnorr::verify_batch(&mut rand::thread_rng(), pubkey, sigs)
+    });
+}
+
+fn bench_abe(bencher: &mut Criterion) {
+    bench_scheme::<Abe, _>(bencher, "Abe verification", Abe::verify_all);
+}
+
+criterion_group!(benches, bench_schnorr, bench_abe);
+criterion_main!(benches);
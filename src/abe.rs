@@ -78,15 +78,33 @@
 *
 * Verify(ζ, ζ₁, ρ, ω, σ₁, σ₂, δ, μ, m):
 *   return ω + δ == H₃(ζ, ζ₁, g^ρ y^ω, g^σ₁ ζ₁^δ, h^σ₂ (ζ/ζ₁)^δ, z^μ ζ^δ, m):
+*
+* VerifyAll((m₁, σ₁), ..., (mₙ, σₙ)):
+*   for i in 1..n:
+*     if not Verify(σᵢ, mᵢ): return false
+*   return true
+*
+*   Each Verify here is in variable time, with each group element computed in a single
+*   multiscalar multiplication. The group elements that go into H₃ are different for every
+*   signature, so the checks can't be combined into one.
+*
+* Partially blind signatures:
+*   The signer and client can agree on some public info beforehand, and bind it to the signature by
+*   using the tag key z := H₁(h, y, info) everywhere above instead of the fixed one. Everything
+*   else stays the same. The fixed tag key is the tag key for the empty info.
 */
 
-use crate::common::{FourMoveBlindSig, GroupElem, Scalar};
+use crate::{
+    common::{join_encoding, split_encoding, FourMoveBlindSig, GroupElem, Scalar},
+    error::Error,
+};
 
 use blake2::{crypto_mac::Mac, digest::Digest, Blake2b};
 use curve25519_dalek::{
     constants::RISTRETTO_BASEPOINT_TABLE,
     ristretto::{RistrettoBasepointTable, RistrettoPoint},
     scalar::Scalar as ScalarRepr,
+    traits::VartimeMultiscalarMul,
 };
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
@@ -112,7 +130,6 @@ pub struct Pubkey {
 #[derive(Clone, Copy, Deserialize, Serialize)]
 pub struct Privkey(Scalar);
 
-#[derive(Clone, Copy, Default, Deserialize, Serialize)]
 pub struct ServerState {
     u: Scalar,
     s1: Scalar,
@@ -165,9 +182,241 @@ pub struct Signature {
     μ: Scalar,
 }
 
+// Canonical byte encodings. Scalars are 32 bytes little-endian, and group elements are 32-byte
+// compressed Ristretto points. Structs are the concatenation of their fields in order.
+
+impl Privkey {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Privkey, Error> {
+        Scalar::from_bytes(bytes).map(Privkey)
+    }
+}
+
+impl Pubkey {
+    /// Encodes the public key as y. The tag key z is derived from y, so it's left out.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.y.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Pubkey, Error> {
+        let y = GroupElem::from_bytes(bytes)?;
+        let z = tag_key(&y, b"");
+        Ok(Pubkey { y, z })
+    }
+}
+
+impl ServerResp1 {
+    /// Encodes the response as rnd || a || b₁ || b₂
+    pub fn to_bytes(&self) -> [u8; 128] {
+        join_encoding(&[
+            self.rnd,
+            self.a.to_bytes(),
+            self.b1.to_bytes(),
+            self.b2.to_bytes(),
+        ])
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ServerResp1, Error> {
+        let [rnd, a, b1, b2] = split_encoding::<4>(bytes, "first server response")?;
+        Ok(ServerResp1 {
+            rnd,
+            a: GroupElem::from_bytes(&a)?,
+            b1: GroupElem::from_bytes(&b1)?,
+            b2: GroupElem::from_bytes(&b2)?,
+        })
+    }
+}
+
+impl ClientResp {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ClientResp, Error> {
+        Scalar::from_bytes(bytes).map(ClientResp)
+    }
+}
+
+impl ServerResp2 {
+    /// Encodes the response as r || c || s₁ || s₂ || d
+    pub fn to_bytes(&self) -> [u8; 160] {
+        let ServerResp2 { r, c, s1, s2, d } = self;
+        join_encoding(&[
+            r.to_bytes(),
+            c.to_bytes(),
+            s1.to_bytes(),
+            s2.to_bytes(),
+            d.to_bytes(),
+        ])
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ServerResp2, Error> {
+        let [r, c, s1, s2, d] = split_encoding::<5>(bytes, "second server response")?;
+        Ok(ServerResp2 {
+            r: Scalar::from_bytes(&r)?,
+            c: Scalar::from_bytes(&c)?,
+            s1: Scalar::from_bytes(&s1)?,
+            s2: Scalar::from_bytes(&s2)?,
+            d: Scalar::from_bytes(&d)?,
+        })
+    }
+}
+
+impl Signature {
+    /// Encodes the signature as ζ || ζ₁ || ρ || ω || σ₁ || σ₂ || δ || μ
+    pub fn to_bytes(&self) -> [u8; 256] {
+        let Signature {
+            ζ,
+            ζ1,
+            ρ,
+            ω,
+            σ1,
+            σ2,
+            δ,
+            μ,
+        } = self;
+        join_encoding(&[
+            ζ.to_bytes(),
+            ζ1.to_bytes(),
+            ρ.to_bytes(),
+            ω.to_bytes(),
+            σ1.to_bytes(),
+            σ2.to_bytes(),
+            δ.to_bytes(),
+            μ.to_bytes(),
+        ])
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, Error> {
+        let [ζ, ζ1, ρ, ω, σ1, σ2, δ, μ] = split_encoding::<8>(bytes, "signature")?;
+        Ok(Signature {
+            ζ: GroupElem::from_bytes(&ζ)?,
+            ζ1: GroupElem::from_bytes(&ζ1)?,
+            ρ: Scalar::from_bytes(&ρ)?,
+            ω: Scalar::from_bytes(&ω)?,
+            σ1: Scalar::from_bytes(&σ1)?,
+            σ2: Scalar::from_bytes(&σ2)?,
+            δ: Scalar::from_bytes(&δ)?,
+            μ: Scalar::from_bytes(&μ)?,
+        })
+    }
+}
+
 /// The impl of the Abe blind signature scheme
 pub struct Abe;
 
+/// Computes the tag key z := H₁(h, y, info)
+fn tag_key(y: &GroupElem, info: &[u8]) -> GroupElem {
+    GroupElem(RistrettoPoint::from_hash(
+        H1.clone()
+            .chain(RISTRETTO_ALT_GENERATOR.basepoint().compress().to_bytes())
+            .chain(y.0.compress().to_bytes())
+            .chain(info),
+    ))
+}
+
+/// Computes H₃(ζ, ζ₁, α, β₁, β₂, η, m)
+fn verif_hash(
+    ζ: &GroupElem,
+    ζ1: &GroupElem,
+    α: &RistrettoPoint,
+    β1: &RistrettoPoint,
+    β2: &RistrettoPoint,
+    η: &RistrettoPoint,
+    m: &[u8],
+) -> ScalarRepr {
+    ScalarRepr::from_hash(
+        H3.clone()
+            .chain(ζ.to_bytes())
+            .chain(ζ1.to_bytes())
+            .chain(α.compress().to_bytes())
+            .chain(β1.compress().to_bytes())
+            .chain(β2.compress().to_bytes())
+            .chain(η.compress().to_bytes())
+            .chain(m),
+    )
+}
+
+// The partially blind variant of the scheme. These are the same as the `FourMoveBlindSig` methods,
+// except that the signer and client use the tag key for the given public info. sign2 doesn't touch
+// the tag key, so it's unchanged.
+impl Abe {
+    /// Returns the public key whose tag key is bound to the given public info
+    fn pubkey_for_info(pubkey: &Pubkey, info: &[u8]) -> Pubkey {
+        Pubkey {
+            y: pubkey.y,
+            z: tag_key(&pubkey.y, info),
+        }
+    }
+
+    pub fn sign1_with_info<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Pubkey,
+        info: &[u8],
+    ) -> (ServerState, ServerResp1) {
+        Self::sign1(rng, &Self::pubkey_for_info(pubkey, info))
+    }
+
+    pub fn user1_with_info<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Pubkey,
+        info: &[u8],
+        m: &[u8],
+        server_resp1: &ServerResp1,
+    ) -> (ClientState, ClientResp) {
+        Self::user1(rng, &Self::pubkey_for_info(pubkey, info), m, server_resp1)
+    }
+
+    pub fn user2_with_info(
+        pubkey: &Pubkey,
+        info: &[u8],
+        state: &ClientState,
+        m: &[u8],
+        server_resp2: &ServerResp2,
+    ) -> Result<Signature, Error> {
+        Self::user2(&Self::pubkey_for_info(pubkey, info), state, m, server_resp2)
+    }
+
+    pub fn verify_with_info(pubkey: &Pubkey, info: &[u8], m: &[u8], sig: &Signature) -> bool {
+        Self::verify(&Self::pubkey_for_info(pubkey, info), m, sig)
+    }
+}
+
+impl Abe {
+    /// Verifies all the given signatures, one at a time. Returns true iff every signature is
+    /// valid. The hash input depends on each signature, so unlike Blind Schnorr's, the checks
+    /// can't be combined into one.
+    pub fn verify_all(pubkey: &Pubkey, sigs: &[(&[u8], Signature)]) -> bool {
+        let Pubkey { y, z } = pubkey;
+        let h = RISTRETTO_ALT_GENERATOR.basepoint();
+
+        sigs.iter().all(|(m, sig)| {
+            let Signature {
+                ζ,
+                ζ1,
+                ρ,
+                ω,
+                σ1,
+                σ2,
+                δ,
+                μ,
+            } = sig;
+
+            // Intermediate calculations. This is all public data, so variable time is fine.
+            let ζ2 = ζ.0 - ζ1.0;
+            let α = RistrettoPoint::vartime_double_scalar_mul_basepoint(&ω.0, &y.0, &ρ.0);
+            let β1 = RistrettoPoint::vartime_double_scalar_mul_basepoint(&δ.0, &ζ1.0, &σ1.0);
+            let β2 = RistrettoPoint::vartime_multiscalar_mul(&[σ2.0, δ.0], &[h, ζ2]);
+            let η = RistrettoPoint::vartime_multiscalar_mul(&[μ.0, δ.0], &[z.0, ζ.0]);
+
+            verif_hash(ζ, ζ1, &α, &β1, &β2, &η, m) == ω.0 + δ.0
+        })
+    }
+}
+
 impl FourMoveBlindSig for Abe {
     // The Abe scheme can have polynomially many parallel sessions. We'll give it the maximum
     // possible bound and see how it goes.
@@ -190,11 +439,7 @@ impl FourMoveBlindSig for Abe {
         // if z == 1: retry
         let x = Scalar::random(rng);
         let y = GroupElem(&x.0 * &RISTRETTO_BASEPOINT_TABLE);
-        let z = GroupElem(RistrettoPoint::from_hash(
-            H1.clone()
-                .chain(&RISTRETTO_ALT_GENERATOR.basepoint().compress().to_bytes())
-                .chain(y.0.compress().to_bytes()),
-        ));
+        let z = tag_key(&y, b"");
 
         // sk = x
         let privkey = Privkey(x);
@@ -230,18 +475,7 @@ impl FourMoveBlindSig for Abe {
         // ):
         //     abort
         // return (ζ, ζ₁, ρ, ω, σ₁, σ₂, δ, μ)
-        let h = ScalarRepr::from_hash(
-            H3.clone()
-                .chain(ζ.to_bytes())
-                .chain(ζ1.to_bytes())
-                .chain(α.compress().to_bytes())
-                .chain(β1.compress().to_bytes())
-                .chain(β2.compress().to_bytes())
-                .chain(η.compress().to_bytes())
-                .chain(m),
-        );
-
-        h == ω.0 + δ.0
+        verif_hash(ζ, ζ1, &α, &β1, &β2, &η, m) == ω.0 + δ.0
     }
 
     fn sign1<R: RngCore + CryptoRng>(rng: &mut R, pubkey: &Pubkey) -> (ServerState, ServerResp1) {
@@ -252,7 +486,7 @@ impl FourMoveBlindSig for Abe {
         // z₂ := z/z₁
         let mut rnd = [0u8; 32];
         rng.fill_bytes(&mut rnd);
-        let z1 = RistrettoPoint::from_hash(H1.clone().chain(&rnd));
+        let z1 = RistrettoPoint::from_hash(H1.clone().chain(rnd));
         let z2 = z.0 - z1;
 
         // u, s₁, s₂, d ← S
@@ -287,7 +521,7 @@ impl FourMoveBlindSig for Abe {
 
         // z₁ := H₂(rnd)
         // γ ← S*
-        let z1 = RistrettoPoint::from_hash(H1.clone().chain(&rnd));
+        let z1 = RistrettoPoint::from_hash(H1.clone().chain(rnd));
         let mut γ = Scalar(ScalarRepr::zero());
         while γ.0 == ScalarRepr::zero() {
             γ = Scalar::random(rng);
@@ -351,7 +585,7 @@ impl FourMoveBlindSig for Abe {
         (state, resp)
     }
 
-    fn sign2(privkey: &Privkey, state: &ServerState, client_resp: &ClientResp) -> ServerResp2 {
+    fn sign2(privkey: &Privkey, state: ServerState, client_resp: &ClientResp) -> ServerResp2 {
         let Privkey(x) = privkey;
         let ServerState { u, s1, s2, d } = state;
         let ClientResp(e) = client_resp;
@@ -361,13 +595,7 @@ impl FourMoveBlindSig for Abe {
         let c = Scalar(e.0 - d.0);
         let r = Scalar(u.0 - c.0 * x.0);
 
-        ServerResp2 {
-            r,
-            c,
-            s1: *s1,
-            s2: *s2,
-            d: *d,
-        }
+        ServerResp2 { r, c, s1, s2, d }
     }
 
     fn user2(
@@ -375,7 +603,7 @@ impl FourMoveBlindSig for Abe {
         state: &ClientState,
         m: &[u8],
         server_resp2: &ServerResp2,
-    ) -> Option<Signature> {
+    ) -> Result<Signature, Error> {
         let ClientState {
             ζ,
             ζ1,
@@ -416,9 +644,9 @@ impl FourMoveBlindSig for Abe {
         };
 
         if Self::verify(pubkey, m, &tentative_sig) {
-            Some(tentative_sig)
+            Ok(tentative_sig)
         } else {
-            None
+            Err(Error::VerificationFailed)
         }
     }
 }
@@ -432,8 +660,119 @@ fn test_correctness() {
     let (privkey, pubkey) = Alg::keygen(&mut csprng);
     let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
     let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
-    let server_resp2 = Alg::sign2(&privkey, &server_state, &client_resp);
+    let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
     let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
 
     assert!(Alg::verify(&pubkey, m, &sig));
 }
+
+#[test]
+fn test_partially_blind_correctness() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    let info = b"epoch 2020-08";
+    type Alg = Abe;
+
+    let (privkey, pubkey) = Alg::keygen(&mut csprng);
+    let (server_state, server_resp1) = Alg::sign1_with_info(&mut csprng, &pubkey, info);
+    let (client_state, client_resp) =
+        Alg::user1_with_info(&mut csprng, &pubkey, info, m, &server_resp1);
+    let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
+    let sig = Alg::user2_with_info(&pubkey, info, &client_state, m, &server_resp2).unwrap();
+
+    assert!(Alg::verify_with_info(&pubkey, info, m, &sig));
+
+    // The signature is bound to the info it was issued under
+    assert!(!Alg::verify_with_info(&pubkey, b"epoch 2020-09", m, &sig));
+    assert!(!Alg::verify(&pubkey, m, &sig));
+
+    // The fully blind scheme is the partially blind scheme with empty info
+    let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
+    let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
+    let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
+    let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
+    assert!(Alg::verify_with_info(&pubkey, b"", m, &sig));
+}
+
+#[test]
+fn test_verify_all() {
+    let mut csprng = rand::thread_rng();
+    type Alg = Abe;
+
+    let (privkey, pubkey) = Alg::keygen(&mut csprng);
+    let msgs: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; 10]).collect();
+    let mut sigs: Vec<(&[u8], Signature)> = msgs
+        .iter()
+        .map(|m| {
+            let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
+            let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
+            let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
+            let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
+            (m.as_slice(), sig)
+        })
+        .collect();
+
+    assert!(Alg::verify_all(&pubkey, &[]));
+    assert!(Alg::verify_all(&pubkey, &sigs));
+
+    // Swap the messages of two signatures. Now the batch should fail.
+    let m0 = sigs[0].0;
+    sigs[0].0 = sigs[1].0;
+    sigs[1].0 = m0;
+    assert!(!Alg::verify_all(&pubkey, &sigs));
+}
+
+#[test]
+fn test_encoding_roundtrip() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    type Alg = Abe;
+
+    let (privkey, pubkey) = Alg::keygen(&mut csprng);
+    let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
+    let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
+    let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
+    let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
+
+    // Everything should decode to something that encodes to the same bytes
+    let privkey_bytes = privkey.to_bytes();
+    assert_eq!(
+        Privkey::from_bytes(&privkey_bytes).unwrap().to_bytes(),
+        privkey_bytes
+    );
+    let resp1_bytes = server_resp1.to_bytes();
+    assert_eq!(
+        ServerResp1::from_bytes(&resp1_bytes).unwrap().to_bytes(),
+        resp1_bytes
+    );
+    let client_resp_bytes = client_resp.to_bytes();
+    assert_eq!(
+        ClientResp::from_bytes(&client_resp_bytes)
+            .unwrap()
+            .to_bytes(),
+        client_resp_bytes
+    );
+    let resp2_bytes = server_resp2.to_bytes();
+    assert_eq!(
+        ServerResp2::from_bytes(&resp2_bytes).unwrap().to_bytes(),
+        resp2_bytes
+    );
+
+    // The decoded public key should have the same tag key, and a decoded signature should still
+    // verify under it
+    let decoded_pubkey = Pubkey::from_bytes(&pubkey.to_bytes()).unwrap();
+    assert!(decoded_pubkey.z.0 == pubkey.z.0);
+    let sig_bytes = sig.to_bytes();
+    let decoded_sig = Signature::from_bytes(&sig_bytes).unwrap();
+    assert_eq!(decoded_sig.to_bytes(), sig_bytes);
+    assert!(Alg::verify(&decoded_pubkey, m, &decoded_sig));
+
+    // Wrong lengths, non-canonical scalars, and invalid points should all be rejected
+    assert!(Signature::from_bytes(&sig_bytes[1..]).is_err());
+    let mut bad_sig = sig_bytes;
+    bad_sig[64..96].copy_from_slice(&[0xff; 32]);
+    assert!(Signature::from_bytes(&bad_sig).is_err());
+    let mut bad_sig = sig_bytes;
+    bad_sig[..32].copy_from_slice(&[0xff; 32]);
+    assert!(Signature::from_bytes(&bad_sig).is_err());
+}
@@ -0,0 +1,228 @@
+/*
+An async alternative to the rouille server in webserver.rs. rouille gives every request in flight a
+thread of its own, from when it's read until its response is written. That includes the simulated
+latency pause, so with a 30ms pause a pool of n threads can't get through more than n/30 requests
+per millisecond, no matter how cheap the crypto is. Some of Abe's advantage over sequential
+Schnorr on that server comes down to the thread count.
+
+Here, requests are tokio tasks on a single thread. The decoding, the session store and the crypto
+run on tokio's blocking pool, which has pool_size threads, and the latency pause is a timer, so a
+paused request doesn't hold on to any thread at all. Whatever gap between the schemes is left is
+down to the crypto and MAX_PARALLEL_SESSIONS.
+
+The messages and status codes are the same as rouille's, so the clients can't tell the difference.
+*/
+
+use crate::{
+    common::BlindSigProtocol,
+    error::Error,
+    session::SessionStore,
+    transport::{Envelope, SESSION_TOKEN_HEADER},
+    webserver::{status_code, SessionHandler},
+};
+use std::{
+    convert::Infallible,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
+};
+
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+    Request, Response, StatusCode,
+};
+use hyper_util::{rt::TokioIo, server::graceful::GracefulShutdown};
+use rand::distributions::Distribution;
+
+// Every response is small enough to send in one go
+type Body = Full<Bytes>;
+
+fn error_response(err: &Error) -> Response<Body> {
+    Response::builder()
+        .status(status_code(err))
+        .body(Body::from(err.to_string()))
+        .unwrap()
+}
+
+async fn handle<S, D>(
+    handler: Arc<SessionHandler<S, D>>,
+    req: Request<Incoming>,
+) -> Result<Response<Body>, Infallible>
+where
+    S: BlindSigProtocol + 'static,
+    D: Distribution<f64> + Send + Sync + 'static,
+{
+    if req.uri().path() != "/sign" {
+        let not_found = Response::builder().status(StatusCode::NOT_FOUND);
+        return Ok(not_found.body(Body::default()).unwrap());
+    }
+
+    let token = req
+        .headers()
+        .get(SESSION_TOKEN_HEADER)
+        .and_then(|token| token.to_str().ok())
+        .map(str::to_string);
+    let body = match req.into_body().collect().await {
+        Ok(body) => body.to_bytes().to_vec(),
+        Err(e) => return Ok(error_response(&Error::Transport(e.to_string()))),
+    };
+    let req = Envelope { token, body };
+
+    // Everything that can block, including waiting for a slot if the admission policy says to,
+    // happens on the blocking pool
+    let processed = tokio::task::spawn_blocking(move || handler.process(&req)).await;
+    let (res, pause_time) = match processed {
+        Ok(processed) => processed,
+        Err(e) => return Ok(error_response(&Error::Transport(e.to_string()))),
+    };
+    if !pause_time.is_zero() {
+        tokio::time::sleep(pause_time).await;
+    }
+
+    let resp = match res {
+        Ok(Envelope { token, body }) => {
+            let mut resp = Response::builder().header("Content-Type", "application/json");
+            if let Some(token) = token {
+                resp = resp.header(SESSION_TOKEN_HEADER, token);
+            }
+            resp.body(Body::from(body)).unwrap()
+        }
+        Err(e) => error_response(&e),
+    };
+    Ok(resp)
+}
+
+/// Starts an async HTTP server on the given address, with `pool_size` threads for the crypto.
+/// Returns the server's keypair, and a flag that stops the server when it's set to true.
+pub fn start_async_server<S, D>(
+    addr: &str,
+    pool_size: usize,
+    global_state: Arc<SessionStore<S::ServerSession>>,
+    latency_distr: D,
+) -> (S::Privkey, S::Pubkey, Arc<AtomicBool>)
+where
+    S: BlindSigProtocol + 'static,
+    D: Distribution<f64> + Send + Sync + 'static,
+{
+    let handler = Arc::new(SessionHandler::<S, D>::new(
+        pool_size,
+        global_state,
+        latency_distr,
+    ));
+    let (privkey, pubkey) = handler.keypair();
+
+    let stop_var = Arc::new(AtomicBool::new(false));
+    let stop_var_copy = stop_var.clone();
+
+    // Bind right away, so that clients that connect before the runtime is up just wait in the
+    // backlog
+    let listener = TcpListener::bind(addr).expect("couldn't make server");
+    listener
+        .set_nonblocking(true)
+        .expect("couldn't make server");
+
+    std::thread::spawn(move || {
+        // One thread runs the tasks, and the blocking pool gets the rest
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .max_blocking_threads(pool_size)
+            .enable_all()
+            .build()
+            .expect("couldn't start runtime");
+
+        runtime.block_on(async move {
+            let listener =
+                tokio::net::TcpListener::from_std(listener).expect("couldn't make server");
+            let graceful = GracefulShutdown::new();
+            // Check whether we've been told to stop as often as rouille's server would
+            let mut stop_check = tokio::time::interval(Duration::from_millis(10));
+
+            loop {
+                tokio::select! {
+                    conn = listener.accept() => {
+                        let stream = match conn {
+                            Ok((stream, _)) => stream,
+                            Err(e) => {
+                                eprintln!("server error: {}", e);
+                                continue;
+                            }
+                        };
+                        let handler = handler.clone();
+                        let service = service_fn(move |req| handle(handler.clone(), req));
+                        let conn = http1::Builder::new()
+                            .serve_connection(TokioIo::new(stream), service);
+                        let conn = graceful.watch(conn);
+                        // A connection only fails if its client hangs up mid-request, which is
+                        // the client's problem
+                        tokio::spawn(async move {
+                            let _ = conn.await;
+                        });
+                    }
+                    _ = stop_check.tick() => {
+                        if stop_var.load(SeqCst) {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            // Stop taking connections, and let the requests in flight finish
+            drop(listener);
+            graceful.shutdown().await;
+        });
+    });
+
+    (privkey, pubkey, stop_var_copy)
+}
+
+// Clients can't tell the async server from the thread pool, even when they're turned away
+#[test]
+fn test_async_server() {
+    use crate::{
+        retry::RetryPolicy, schnorr::BlindSchnorr, session::AdmissionPolicy,
+        transport::HttpTransport, webserver::make_client,
+    };
+    type S = BlindSchnorr;
+
+    let server_addr = "localhost:23493";
+    let timeout = Duration::from_secs(10);
+    let my_global_state = Arc::new(SessionStore::new(timeout, AdmissionPolicy::Reject));
+    let latency_distr = rand_distr::Normal::new(20f64, 5f64).unwrap();
+    let (_privkey, pubkey, stop_var) =
+        start_async_server::<S, _>(server_addr, 1, my_global_state, latency_distr);
+
+    // Sequential Schnorr only takes one session at a time, so most of these get turned away at
+    // first
+    let mut threads = Vec::new();
+    for _ in 0..10 {
+        let client = make_client::<S, _>(
+            HttpTransport::new(server_addr),
+            pubkey,
+            RetryPolicy::default(),
+        );
+        threads.push(std::thread::spawn(client));
+    }
+    let num_retries: u32 = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap().unwrap().num_retries)
+        .sum();
+    assert!(num_retries > 0);
+
+    let status = reqwest::blocking::Client::new()
+        .post(&format!("http://{}/sign", server_addr))
+        .body("hello")
+        .send()
+        .unwrap()
+        .status();
+    assert_eq!(status.as_u16(), 400);
+
+    // Kill the server, and wait a second for it to get the message and free up the port
+    stop_var.store(true, SeqCst);
+    std::thread::sleep(Duration::from_secs(1));
+}
@@ -0,0 +1,140 @@
+use blind_sig_bench::compare::{compare_dirs, Comparison};
+use std::process::exit;
+
+const USAGE: &str = "\
+Usage: compare [OPTIONS] BASELINE_DIR CANDIDATE_DIR
+
+Compares two Criterion output directories of webserver benchmark results, e.g.,
+ec2_data/webserver_bench and target/criterion. Exits with status 1 if any benchmark got
+significantly slower by more than the threshold.
+
+Options:
+  --threshold PCT      How much slower, in percent, a benchmark can get before it counts as a
+                       regression [5]
+  --help               Print this message";
+
+/// Parses the value of the given flag, or exits with an explanation
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T
+where
+    T::Err: std::fmt::Display,
+{
+    let value = value.unwrap_or_else(|| {
+        eprintln!("{} needs a value\n\n{}", flag, USAGE);
+        exit(2)
+    });
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("invalid value {:?} for {}: {}", value, flag, e);
+        exit(2)
+    })
+}
+
+/// Formats a fraction as a signed percentage
+fn percent(x: f64) -> String {
+    format!("{:+.1}%", 100.0 * x)
+}
+
+/// Prints one row of the table
+fn print_row(c: &Comparison, threshold: f64) {
+    let (lower, upper) = c.change_interval();
+    let verdict = if c.is_regression(threshold) {
+        "REGRESSED"
+    } else if !c.is_significant() {
+        "no change"
+    } else if c.relative_change() < 0.0 {
+        "improved"
+    } else {
+        "slower"
+    };
+
+    println!(
+        "{:<30} {:>4} {:>7} {:>8} {:>10.3} {:>10.3} {:>8} {:>19} {}",
+        c.id.group,
+        c.id.pool_size,
+        c.id.num_clients,
+        c.id.eiat_ms,
+        c.baseline.mean / 1e9,
+        c.candidate.mean / 1e9,
+        percent(c.relative_change()),
+        format!("[{}, {}]", percent(lower), percent(upper)),
+        verdict
+    );
+}
+
+fn main() {
+    let mut threshold_pct: f64 = 5.0;
+    let mut dirs = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => threshold_pct = parse_value(&arg, args.next()),
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option {:?}\n\n{}", arg, USAGE);
+                exit(2)
+            }
+            _ => dirs.push(arg),
+        }
+    }
+    let (baseline_dir, candidate_dir) = match dirs.as_slice() {
+        [baseline, candidate] => (baseline, candidate),
+        _ => {
+            eprintln!("expected two directories\n\n{}", USAGE);
+            exit(2)
+        }
+    };
+    if !threshold_pct.is_finite() || threshold_pct < 0.0 {
+        eprintln!("--threshold must be a percentage of at least 0");
+        exit(2)
+    }
+    let threshold = threshold_pct / 100.0;
+
+    let set = compare_dirs(baseline_dir, candidate_dir).unwrap_or_else(|e| {
+        eprintln!("couldn't read results: {}", e);
+        exit(2)
+    });
+    if set.comparisons.is_empty() {
+        eprintln!(
+            "{} and {} have no benchmarks in common",
+            baseline_dir, candidate_dir
+        );
+        exit(2)
+    }
+
+    println!(
+        "{:<30} {:>4} {:>7} {:>8} {:>10} {:>10} {:>8} {:>19} verdict",
+        "group", "pool", "clients", "EIAT(ms)", "base(s)", "new(s)", "change", "interval"
+    );
+    for c in &set.comparisons {
+        print_row(c, threshold);
+    }
+
+    for (ids, dir) in &[
+        (&set.only_baseline, baseline_dir),
+        (&set.only_candidate, candidate_dir),
+    ] {
+        if !ids.is_empty() {
+            println!("\nOnly in {}:", dir);
+            for id in ids.iter() {
+                println!(
+                    "  {}: {}-core, {} clients, {}ms EIAT",
+                    id.group, id.pool_size, id.num_clients, id.eiat_ms
+                );
+            }
+        }
+    }
+
+    let num_regressions = set.regressions(threshold).count();
+    println!(
+        "\n{} of {} benchmarks regressed by more than {}%",
+        num_regressions,
+        set.comparisons.len(),
+        threshold_pct
+    );
+    if num_regressions > 0 {
+        exit(1)
+    }
+}
@@ -0,0 +1,130 @@
+use blind_sig_bench::{
+    loadgen::{run, LoadConfig, TransportKind},
+    scenario::{load_file, LatencyModel},
+    session::AdmissionPolicy,
+};
+use std::{fs::File, io, process::exit, time::Duration};
+
+const USAGE: &str = "\
+Usage: loadgen [OPTIONS]
+
+Puts a blind signing server under Poisson load and reports session latency percentiles as JSON.
+
+Options:
+  --scheme NAME        blind-schnorr, clause-blind-schnorr, abe, blind-rsa or blind-bls [abe]
+  --duration SECS      How long new sessions keep arriving for [30]
+  --rate N             Mean new sessions per second [10]
+  --pool-size N        Number of server threads [4]
+  --latency-mean MS    Mean latency the server simulates after each response [30]
+  --latency-std MS     Standard deviation of that latency [5]
+  --latency PATH       Read the latency model from a TOML or JSON file instead, in the same
+                       format as the latency field of a scenario
+  --seed N             Seed the arrival schedule and latency, so every run gets the same workload
+  --queue              Queue new sessions when the server is full, instead of turning them away.
+                       Up to pool-size - 1 wait at once, and the rest are still turned away
+  --addr HOST:PORT     Address the server listens on [localhost:14148]
+  --backend NAME       thread-pool or async [thread-pool]
+  --in-memory          Talk to the server over channels instead of HTTP. Only works with the
+                       thread-pool backend
+  --max-in-flight N    Most sessions in flight at once. Arrivals past that are dropped, and counted
+                       in the report [1000]
+  --out PATH           Where to write the JSON report [stdout]
+  --help               Print this message";
+
+/// Parses the value of the given flag, or exits with an explanation
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T
+where
+    T::Err: std::fmt::Display,
+{
+    let value = value.unwrap_or_else(|| {
+        eprintln!("{} needs a value\n\n{}", flag, USAGE);
+        exit(2)
+    });
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("invalid value {:?} for {}: {}", value, flag, e);
+        exit(2)
+    })
+}
+
+fn main() -> io::Result<()> {
+    let mut config = LoadConfig::default();
+    let mut in_memory = false;
+    let mut out_path: Option<String> = None;
+    let (mut latency_mean, mut latency_std) = (30.0, 5.0);
+    let mut latency_path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--scheme" => config.scheme = parse_value(&flag, args.next()),
+            "--duration" => {
+                let secs: f64 = parse_value(&flag, args.next());
+                config.duration = Duration::try_from_secs_f64(secs).unwrap_or_else(|e| {
+                    eprintln!("invalid value {:?} for --duration: {}", secs, e);
+                    exit(2)
+                });
+            }
+            "--rate" => config.arrival_rate = parse_value(&flag, args.next()),
+            "--pool-size" => config.pool_size = parse_value(&flag, args.next()),
+            "--latency-mean" => latency_mean = parse_value(&flag, args.next()),
+            "--latency-std" => latency_std = parse_value(&flag, args.next()),
+            "--latency" => latency_path = Some(parse_value::<String>(&flag, args.next())),
+            "--seed" => config.seed = Some(parse_value(&flag, args.next())),
+            "--queue" => config.admission = AdmissionPolicy::Queue,
+            "--addr" => config.transport = TransportKind::Http(parse_value(&flag, args.next())),
+            "--backend" => config.server_backend = parse_value(&flag, args.next()),
+            "--in-memory" => in_memory = true,
+            "--max-in-flight" => config.max_in_flight = parse_value(&flag, args.next()),
+            "--out" => out_path = Some(parse_value(&flag, args.next())),
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => {
+                eprintln!("unknown option {:?}\n\n{}", flag, USAGE);
+                exit(2)
+            }
+        }
+    }
+    if in_memory {
+        config.transport = TransportKind::InMemory;
+    }
+    config.latency = match latency_path {
+        Some(path) => load_file(&path).unwrap_or_else(|e| {
+            eprintln!("couldn't read latency model from {}: {}", path, e);
+            exit(2)
+        }),
+        None => LatencyModel::Normal {
+            mean_ms: latency_mean,
+            std_ms: latency_std,
+        },
+    };
+    if let Err(e) = config.check() {
+        eprintln!("{}", e);
+        exit(2)
+    }
+
+    let report = run(&config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1)
+    });
+    eprintln!(
+        "{}: {}/{} sessions completed, {} dropped, {:.1} sessions/s, p99 {:.1}ms",
+        config.scheme.name(),
+        report.sessions_completed,
+        report.sessions_started,
+        report.sessions_dropped,
+        report.throughput,
+        report.latency_ms.p99
+    );
+
+    match out_path {
+        Some(path) => serde_json::to_writer_pretty(File::create(path)?, &report)?,
+        None => {
+            serde_json::to_writer_pretty(io::stdout(), &report)?;
+            println!();
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use blind_sig_bench::{
+    compare::compare_dirs,
+    scenario::Scenario,
+    sim::{simulate_scenario, write_results},
+};
+use std::{error::Error, path::PathBuf, process::exit};
+
+const USAGE: &str = "\
+Usage: simulate [OPTIONS]
+
+Simulates the webserver benchmark in virtual time, using the per-step benchmark's measurements for
+the cost of the crypto. Results are written the way Criterion writes them, so the plotter and the
+comparison tool can read them.
+
+Options:
+  --scenario PATH      The sweep to simulate [$BENCH_SCENARIO, or scenarios/ec2.toml]
+  --piecewise-dir DIR  Per-step benchmark results [./ec2_data/piecewise_bench]
+  --iterations N       Number of runs of each benchmark [100]
+  --out-dir DIR        Where to write the results [sim_data/webserver_bench]
+  --validate DIR       Compare the results against measured webserver benchmark results, e.g.,
+                       ./ec2_data/webserver_bench [none]
+  --help               Print this message";
+
+/// Parses the value of the given flag, or exits with an explanation
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T
+where
+    T::Err: std::fmt::Display,
+{
+    let value = value.unwrap_or_else(|| {
+        eprintln!("{} needs a value\n\n{}", flag, USAGE);
+        exit(2)
+    });
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("invalid value {:?} for {}: {}", value, flag, e);
+        exit(2)
+    })
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut scenario_path: Option<String> = None;
+    let mut piecewise_dir = PathBuf::from("./ec2_data/piecewise_bench");
+    let mut iterations = 100;
+    let mut out_dir = PathBuf::from("sim_data/webserver_bench");
+    let mut validate_dir: Option<PathBuf> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--scenario" => scenario_path = Some(parse_value(&flag, args.next())),
+            "--piecewise-dir" => piecewise_dir = parse_value(&flag, args.next()),
+            "--iterations" => iterations = parse_value(&flag, args.next()),
+            "--out-dir" => out_dir = parse_value(&flag, args.next()),
+            "--validate" => validate_dir = Some(parse_value(&flag, args.next())),
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => {
+                eprintln!("unknown option {:?}\n\n{}", flag, USAGE);
+                exit(2)
+            }
+        }
+    }
+    if iterations < 2 {
+        eprintln!("--iterations must be at least 2");
+        exit(2)
+    }
+
+    let scenario = match scenario_path {
+        Some(path) => Scenario::load(path)?,
+        None => Scenario::from_env()?,
+    };
+    let (results, skipped) = simulate_scenario(&scenario, &piecewise_dir, iterations)?;
+    for scheme in skipped {
+        eprintln!(
+            "no per-step results for {} in {}, skipping",
+            scheme.name(),
+            piecewise_dir.display()
+        );
+    }
+
+    for r in &results {
+        println!(
+            "{}/{}: {:.3}s ± {:.3}s, {} retries, {} failed",
+            r.id.group,
+            r.bench_name,
+            r.estimates.mean / 1e9,
+            r.estimates.std_dev / 1e9,
+            r.num_retries,
+            r.num_failed
+        );
+    }
+    write_results(&out_dir, &results)?;
+    println!("Wrote {} results to {}", results.len(), out_dir.display());
+
+    if let Some(measured_dir) = validate_dir {
+        let set = compare_dirs(&measured_dir, &out_dir)?;
+        println!(
+            "\n{:<30} {:>4} {:>8} {:>12} {:>12} {:>8}",
+            "group", "pool", "EIAT(ms)", "measured(s)", "simulated(s)", "error"
+        );
+        for c in &set.comparisons {
+            println!(
+                "{:<30} {:>4} {:>8} {:>12.3} {:>12.3} {:>+7.1}%",
+                c.id.group,
+                c.id.pool_size,
+                c.id.eiat_ms,
+                c.baseline.mean / 1e9,
+                c.candidate.mean / 1e9,
+                100.0 * c.relative_change()
+            );
+        }
+
+        if !set.comparisons.is_empty() {
+            let mean_abs_error = set
+                .comparisons
+                .iter()
+                .map(|c| c.relative_change().abs())
+                .sum::<f64>()
+                / set.comparisons.len() as f64;
+            println!(
+                "\nMean absolute error over {} benchmarks: {:.1}%",
+                set.comparisons.len(),
+                100.0 * mean_abs_error
+            );
+        } else {
+            println!(
+                "\nnothing in {} to validate against",
+                measured_dir.display()
+            );
+        }
+    }
+
+    Ok(())
+}
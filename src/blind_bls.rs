@@ -0,0 +1,271 @@
+/* The blind BLS signature scheme, from
+ * https://link.springer.com/chapter/10.1007/3-540-36288-6_3 (Boldyreva, PKC 2003)
+ *
+ * Signatures live in G₁ and public keys live in G₂. H hashes to G₁ as in RFC 9380, using the
+ * BLS12381G1_XMD:SHA-256_SSWU_RO_ suite.
+ *
+ * x is the secret key
+ * X is the public key
+ * g₂ is the generator of G₂
+ * S* is the set of nonzero scalars
+ * e is the pairing
+ * m is the message
+ *
+ *         Signer(x)                            Client(X, m)
+ *         ----------                           ------------
+ *                                              Hash the message and blind it
+ *
+ *                                              r ← S*
+ *                                              B := r·H(m)
+ *                                  B
+ *                                <-----
+ * Sign the blinded message
+ *
+ * S := x·B
+ *                                  S
+ *                                ----->
+ *                                              Unblind the signature and
+ *                                              check it
+ *
+ *                                              σ := r⁻¹·S
+ *                                              if e(σ, g₂) ≠ e(H(m), X): abort
+ *
+ * KeyGen():
+ *   x ← S*
+ *   X := x·g₂
+ *   return (x, X)
+ *
+ * Verify(X, m, σ):
+ *   return e(σ, g₂) == e(H(m), X)
+ */
+
+use crate::{
+    common::{BlindSigProtocol, ClientStep, ServerStep},
+    error::Error,
+};
+
+use bls12_381::{
+    hash_to_curve::{ExpandMsgXmd, HashToCurve},
+    multi_miller_loop, G1Affine, G1Projective, G2Affine, G2Prepared, G2Projective, Gt,
+    Scalar as ScalarRepr,
+};
+use rand::{CryptoRng, RngCore};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+// The domain separation tag for hashing to G₁. This is the one the IETF BLS signature draft uses
+// for its basic scheme with signatures in G₁.
+const HASH_TO_G1_DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+
+fn serialize_g1_point<S: Serializer>(point: &G1Affine, ser: S) -> Result<S::Ok, S::Error> {
+    let bytes = point.to_compressed();
+    ser.serialize_bytes(&bytes)
+}
+
+fn deserialize_g1_point<'de, D: Deserializer<'de>>(de: D) -> Result<G1Affine, D::Error> {
+    // serde doesn't do arrays longer than 32, so go through a Vec
+    let bytes = <Vec<u8>>::deserialize(de)?;
+    let mut compressed = [0u8; 48];
+    if bytes.len() != compressed.len() {
+        return Err(D::Error::invalid_length(bytes.len(), &"48 bytes"));
+    }
+    compressed.copy_from_slice(&bytes);
+
+    // This checks that the point is in the prime-order subgroup
+    Option::from(G1Affine::from_compressed(&compressed))
+        .ok_or_else(|| D::Error::custom("encountered an invalid G1 point"))
+}
+
+fn serialize_g2_point<S: Serializer>(point: &G2Affine, ser: S) -> Result<S::Ok, S::Error> {
+    let bytes = point.to_compressed();
+    ser.serialize_bytes(&bytes)
+}
+
+fn deserialize_g2_point<'de, D: Deserializer<'de>>(de: D) -> Result<G2Affine, D::Error> {
+    // serde doesn't do arrays longer than 32, so go through a Vec
+    let bytes = <Vec<u8>>::deserialize(de)?;
+    let mut compressed = [0u8; 96];
+    if bytes.len() != compressed.len() {
+        return Err(D::Error::invalid_length(bytes.len(), &"96 bytes"));
+    }
+    compressed.copy_from_slice(&bytes);
+
+    // This checks that the point is in the prime-order subgroup
+    Option::from(G2Affine::from_compressed(&compressed))
+        .ok_or_else(|| D::Error::custom("encountered an invalid G2 point"))
+}
+
+/// An element of G₁, which is where messages and signatures live
+#[derive(Copy, Clone, Deserialize, Serialize)]
+pub struct G1Elem(
+    #[serde(
+        serialize_with = "serialize_g1_point",
+        deserialize_with = "deserialize_g1_point"
+    )]
+    pub G1Affine,
+);
+
+/// An element of G₂, which is where public keys live
+#[derive(Copy, Clone, Deserialize, Serialize)]
+pub struct G2Elem(
+    #[serde(
+        serialize_with = "serialize_g2_point",
+        deserialize_with = "deserialize_g2_point"
+    )]
+    pub G2Affine,
+);
+
+/// Samples a uniform nonzero scalar
+fn random_nonzero_scalar<R: RngCore + CryptoRng>(rng: &mut R) -> ScalarRepr {
+    loop {
+        // Reduce 512 bits mod the group order so the result is statistically uniform
+        let mut bytes = [0u8; 64];
+        rng.fill_bytes(&mut bytes);
+        let s = ScalarRepr::from_bytes_wide(&bytes);
+        if s != ScalarRepr::zero() {
+            return s;
+        }
+    }
+}
+
+/// H(m), hashed to G₁ as in RFC 9380
+fn hash_to_g1(m: &[u8]) -> G1Projective {
+    <G1Projective as HashToCurve<ExpandMsgXmd<sha2::Sha256>>>::hash_to_curve(m, HASH_TO_G1_DST)
+}
+
+#[derive(Clone, Copy)]
+pub struct Privkey(ScalarRepr);
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Pubkey(G2Elem);
+
+pub struct ClientSession {
+    r_inv: ScalarRepr,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct BlindedMsg(G1Elem);
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct BlindSig(G1Elem);
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Signature(G1Elem);
+
+/// The impl of the blind BLS signature scheme
+pub struct BlindBls;
+
+impl BlindSigProtocol for BlindBls {
+    // Blind BLS is secure under polynomially many concurrent sessions (under the chosen-target CDH
+    // assumption)
+    const MAX_PARALLEL_SESSIONS: usize = usize::MAX;
+
+    type Privkey = Privkey;
+    type Pubkey = Pubkey;
+
+    // The server speaks exactly once, so it never has to remember anything
+    type ServerSession = ();
+    type ClientSession = ClientSession;
+    type ClientMsg = BlindedMsg;
+    type ServerMsg = BlindSig;
+    type Signature = Signature;
+
+    fn keygen<R: RngCore + CryptoRng>(rng: &mut R) -> (Privkey, Pubkey) {
+        // x ← S*, X := x·g₂
+        let x = random_nonzero_scalar(rng);
+        let X = G2Affine::from(G2Projective::generator() * x);
+
+        (Privkey(x), Pubkey(G2Elem(X)))
+    }
+
+    fn verify(pubkey: &Pubkey, m: &[u8], sig: &Signature) -> bool {
+        let Pubkey(X) = pubkey;
+        let Signature(σ) = sig;
+        let Hm = G1Affine::from(hash_to_g1(m));
+
+        // Check e(σ, g₂) == e(H(m), X) by checking e(σ, -g₂) · e(H(m), X) == 1. This shares the
+        // final exponentiation between the two pairings.
+        let neg_g2 = G2Prepared::from(-G2Affine::generator());
+        let X = G2Prepared::from(X.0);
+        multi_miller_loop(&[(&σ.0, &neg_g2), (&Hm, &X)]).final_exponentiation() == Gt::identity()
+    }
+
+    fn client_start<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        _pubkey: &Pubkey,
+        m: &[u8],
+    ) -> (ClientSession, BlindedMsg) {
+        // r ← S*, B := r·H(m)
+        let r = random_nonzero_scalar(rng);
+        let B = G1Affine::from(hash_to_g1(m) * r);
+
+        // r is nonzero, so it's invertible
+        let r_inv = r.invert().unwrap();
+
+        (ClientSession { r_inv }, BlindedMsg(G1Elem(B)))
+    }
+
+    fn client_step<R: RngCore + CryptoRng>(
+        _rng: &mut R,
+        pubkey: &Pubkey,
+        m: &[u8],
+        session: ClientSession,
+        server_msg: &BlindSig,
+    ) -> Result<ClientStep<Self>, Error> {
+        let ClientSession { r_inv } = session;
+        let BlindSig(S) = server_msg;
+
+        // σ := r⁻¹·S
+        let σ = Signature(G1Elem(G1Affine::from(S.0 * r_inv)));
+
+        // Check the unblinded signature
+        if Self::verify(pubkey, m, &σ) {
+            Ok(ClientStep::Done(σ))
+        } else {
+            Err(Error::VerificationFailed)
+        }
+    }
+
+    fn server_step<R: RngCore + CryptoRng>(
+        _rng: &mut R,
+        privkey: &Privkey,
+        _pubkey: &Pubkey,
+        session: Option<()>,
+        client_msg: &BlindedMsg,
+    ) -> Result<ServerStep<Self>, Error> {
+        // There's only one move, so there should be no existing session
+        if session.is_some() {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        // S := x·B
+        let Privkey(x) = privkey;
+        let BlindedMsg(B) = client_msg;
+        let S = G1Affine::from(B.0 * x);
+
+        Ok(ServerStep::Done(BlindSig(G1Elem(S))))
+    }
+}
+
+#[test]
+fn test_correctness() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    type Alg = BlindBls;
+
+    let (privkey, pubkey) = Alg::keygen(&mut csprng);
+    let (client_session, client_msg) = Alg::client_start(&mut csprng, &pubkey, m);
+    let server_msg = match Alg::server_step(&mut csprng, &privkey, &pubkey, None, &client_msg) {
+        Ok(ServerStep::Done(msg)) => msg,
+        _ => panic!("server didn't finish in one move"),
+    };
+    let sig = match Alg::client_step(&mut csprng, &pubkey, m, client_session, &server_msg) {
+        Ok(ClientStep::Done(sig)) => sig,
+        _ => panic!("client didn't finish in one move"),
+    };
+
+    assert!(Alg::verify(&pubkey, m, &sig));
+    assert!(!Alg::verify(&pubkey, b"Goodbye world", &sig));
+
+    // Unblinded, the signature is just x·H(m)
+    assert!(sig.0 .0 == G1Affine::from(hash_to_g1(m) * privkey.0));
+}
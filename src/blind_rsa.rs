@@ -0,0 +1,424 @@
+/* The RSA blind signature scheme RSABSSA-SHA384-PSS, from
+ * https://www.rfc-editor.org/rfc/rfc9474.html
+ *
+ * (n, e) is the public key
+ * d is the secret key
+ * k is the byte length of n
+ * m is the message
+ *
+ *         Signer(n, d)                         Client(n, e, m)
+ *         ------------                         ---------------
+ *                                              Prepare the message, encode it,
+ *                                              and blind it
+ *
+ *                                              msg := prefix || m
+ *                                              encoded := EMSA-PSS-ENCODE(msg)
+ *                                              r ← ℤ/nℤ*
+ *                                              z := encoded · rᵉ mod n
+ *                                  z
+ *                                <-----
+ * Sign the blinded message
+ *
+ * s := zᵈ mod n
+ * if sᵉ ≠ z mod n: abort
+ *                                  s
+ *                                ----->
+ *                                              Unblind the signature and
+ *                                              check it
+ *
+ *                                              σ := s · r⁻¹ mod n
+ *                                              if !RSASSA-PSS-VERIFY(msg, σ): abort
+ *
+ * In the randomized variant, prefix is 32 random bytes that are sent along with the signature. In
+ * the deterministic variant, prefix is empty.
+ *
+ * Verify(m, (prefix, σ)):
+ *   return RSASSA-PSS-VERIFY(prefix || m, σ)
+ */
+
+use crate::{
+    common::{BlindSigProtocol, ClientStep, ServerStep},
+    error::Error,
+};
+
+use num_bigint_dig::ModInverse;
+use rand::{CryptoRng, RngCore};
+use rsa::{internals, BigUint, PublicKeyParts, RSAPrivateKey, RSAPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+
+// The size of the RSA modulus. RFC 9474 requires at least 2048 bits.
+const MODULUS_BITS: usize = 2048;
+
+// The length of the PSS salt. For RSABSSA-SHA384-PSS this is the length of a SHA-384 digest.
+const SALT_LEN: usize = 48;
+
+// The length of the random message prefix in the randomized variant
+const PREFIX_LEN: usize = 32;
+
+#[derive(Clone)]
+pub struct Privkey(RSAPrivateKey);
+#[derive(Clone)]
+pub struct Pubkey(RSAPublicKey);
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BlindedMsg(Vec<u8>);
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BlindSig(Vec<u8>);
+
+pub struct ClientSession {
+    inv: BigUint,
+    prefix: Option<[u8; PREFIX_LEN]>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Signature {
+    prefix: Option<[u8; PREFIX_LEN]>,
+    s: Vec<u8>,
+}
+
+/// The impl of RSABSSA-SHA384-PSS. If `RANDOMIZED` is set, the client prepends a random prefix to
+/// every message before it's signed.
+pub struct BlindRsa<const RANDOMIZED: bool>;
+
+/// RSABSSA-SHA384-PSS-Randomized
+pub type BlindRsaRandomized = BlindRsa<true>;
+/// RSABSSA-SHA384-PSS-Deterministic
+pub type BlindRsaDeterministic = BlindRsa<false>;
+
+/// The byte length of the modulus, i.e., k in RFC 9474
+fn modulus_len<K: PublicKeyParts>(key: &K) -> usize {
+    key.n().bits().div_ceil(8)
+}
+
+/// I2OSP from RFC 8017. Returns `None` if `x` doesn't fit in `len` bytes.
+fn i2osp(x: &BigUint, len: usize) -> Option<Vec<u8>> {
+    let bytes = x.to_bytes_be();
+    if bytes.len() > len {
+        return None;
+    }
+
+    let mut out = vec![0u8; len - bytes.len()];
+    out.extend_from_slice(&bytes);
+    Some(out)
+}
+
+/// MGF1 from RFC 8017, instantiated with SHA-384. XORs the mask into `out`.
+fn mgf1_xor(seed: &[u8], out: &mut [u8]) {
+    for (counter, chunk) in out.chunks_mut(Sha384::output_size()).enumerate() {
+        let mask = Sha384::new()
+            .chain(seed)
+            .chain((counter as u32).to_be_bytes())
+            .finalize();
+        for (o, m) in chunk.iter_mut().zip(mask.iter()) {
+            *o ^= m;
+        }
+    }
+}
+
+/// Computes H(0x00⁸ || H(msg) || salt), as used in EMSA-PSS
+fn pss_hash(msg: &[u8], salt: &[u8]) -> Vec<u8> {
+    let m_hash = Sha384::digest(msg);
+    Sha384::new()
+        .chain([0u8; 8])
+        .chain(m_hash)
+        .chain(salt)
+        .finalize()
+        .to_vec()
+}
+
+/// EMSA-PSS-ENCODE from RFC 8017, for an encoded message of `em_bits` many bits
+fn emsa_pss_encode<R: RngCore + CryptoRng>(rng: &mut R, msg: &[u8], em_bits: usize) -> Vec<u8> {
+    let h_len = Sha384::output_size();
+    let em_len = em_bits.div_ceil(8);
+
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let h = pss_hash(msg, &salt);
+
+    // DB := PS || 0x01 || salt, where PS is all zeros
+    let mut db = vec![0u8; em_len - h_len - 1];
+    let db_len = db.len();
+    db[db_len - SALT_LEN - 1] = 0x01;
+    db[db_len - SALT_LEN..].copy_from_slice(&salt);
+
+    // maskedDB := DB ⊕ MGF1(H), with the high bits beyond em_bits cleared
+    mgf1_xor(&h, &mut db);
+    db[0] &= 0xff >> (8 * em_len - em_bits);
+
+    // EM := maskedDB || H || 0xbc
+    let mut em = db;
+    em.extend_from_slice(&h);
+    em.push(0xbc);
+    em
+}
+
+/// EMSA-PSS-VERIFY from RFC 8017, for an encoded message of `em_bits` many bits
+fn emsa_pss_verify(msg: &[u8], em: &[u8], em_bits: usize) -> bool {
+    let h_len = Sha384::output_size();
+    let em_len = em_bits.div_ceil(8);
+    if em.len() != em_len || em_len < h_len + SALT_LEN + 2 || em[em_len - 1] != 0xbc {
+        return false;
+    }
+
+    let (masked_db, h) = em[..em_len - 1].split_at(em_len - h_len - 1);
+    if masked_db[0] & !(0xff >> (8 * em_len - em_bits)) != 0 {
+        return false;
+    }
+
+    // Unmask DB and check that it's of the form PS || 0x01 || salt
+    let mut db = masked_db.to_vec();
+    mgf1_xor(h, &mut db);
+    db[0] &= 0xff >> (8 * em_len - em_bits);
+    let (ps, rest) = db.split_at(db.len() - SALT_LEN - 1);
+    if ps.iter().any(|&b| b != 0) || rest[0] != 0x01 {
+        return false;
+    }
+
+    pss_hash(msg, &rest[1..]) == h
+}
+
+/// RSASSA-PSS-VERIFY from RFC 8017
+fn rsassa_pss_verify(pubkey: &RSAPublicKey, msg: &[u8], sig: &[u8]) -> bool {
+    let k = modulus_len(pubkey);
+    if sig.len() != k {
+        return false;
+    }
+
+    let s = BigUint::from_bytes_be(sig);
+    if &s >= pubkey.n() {
+        return false;
+    }
+
+    let em_bits = pubkey.n().bits() - 1;
+    let m = internals::encrypt(pubkey, &s);
+    match i2osp(&m, em_bits.div_ceil(8)) {
+        Some(em) => emsa_pss_verify(msg, &em, em_bits),
+        None => false,
+    }
+}
+
+/// Prepends the message prefix, if there is one
+fn prepare(prefix: &Option<[u8; PREFIX_LEN]>, m: &[u8]) -> Vec<u8> {
+    let mut msg = prefix.map(|p| p.to_vec()).unwrap_or_default();
+    msg.extend_from_slice(m);
+    msg
+}
+
+impl<const RANDOMIZED: bool> BlindSigProtocol for BlindRsa<RANDOMIZED> {
+    // Every signing is a single, stateless RSA operation, so there's no limit on concurrency
+    const MAX_PARALLEL_SESSIONS: usize = usize::MAX;
+
+    type Privkey = Privkey;
+    type Pubkey = Pubkey;
+
+    // The server speaks exactly once, so it never has to remember anything
+    type ServerSession = ();
+    type ClientSession = ClientSession;
+    type ClientMsg = BlindedMsg;
+    type ServerMsg = BlindSig;
+    type Signature = Signature;
+
+    /// Generates an RSA keypair with e = 65537
+    fn keygen<R: RngCore + CryptoRng>(rng: &mut R) -> (Privkey, Pubkey) {
+        let privkey = RSAPrivateKey::new(rng, MODULUS_BITS).expect("couldn't generate RSA key");
+        let pubkey = privkey.to_public_key();
+
+        (Privkey(privkey), Pubkey(pubkey))
+    }
+
+    fn verify(pubkey: &Pubkey, m: &[u8], sig: &Signature) -> bool {
+        // The prefix is there iff this is the randomized variant
+        if sig.prefix.is_some() != RANDOMIZED {
+            return false;
+        }
+
+        rsassa_pss_verify(&pubkey.0, &prepare(&sig.prefix, m), &sig.s)
+    }
+
+    fn client_start<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Pubkey,
+        m: &[u8],
+    ) -> (ClientSession, BlindedMsg) {
+        let Pubkey(pubkey) = pubkey;
+        let n = pubkey.n();
+
+        // msg := prefix || m
+        let prefix = if RANDOMIZED {
+            let mut prefix = [0u8; PREFIX_LEN];
+            rng.fill_bytes(&mut prefix);
+            Some(prefix)
+        } else {
+            None
+        };
+        let msg = prepare(&prefix, m);
+
+        // encoded := EMSA-PSS-ENCODE(msg). If this shares a factor with n, we've factored n, which
+        // happens with negligible probability.
+        let encoded = BigUint::from_bytes_be(&emsa_pss_encode(rng, &msg, n.bits() - 1));
+
+        // r ← ℤ/nℤ*, z := encoded · rᵉ mod n
+        let (r, inv) = loop {
+            let mut r_bytes = vec![0u8; modulus_len(pubkey)];
+            rng.fill_bytes(&mut r_bytes);
+            let r = BigUint::from_bytes_be(&r_bytes) % n;
+            if let Some(inv) = (&r).mod_inverse(n).and_then(|inv| inv.to_biguint()) {
+                break (r, inv);
+            }
+        };
+        let z = (encoded * internals::encrypt(pubkey, &r)) % n;
+
+        let state = ClientSession { inv, prefix };
+        let msg = BlindedMsg(i2osp(&z, modulus_len(pubkey)).unwrap());
+
+        (state, msg)
+    }
+
+    fn client_step<R: RngCore + CryptoRng>(
+        _rng: &mut R,
+        pubkey: &Pubkey,
+        m: &[u8],
+        session: ClientSession,
+        server_msg: &BlindSig,
+    ) -> Result<ClientStep<Self>, Error> {
+        let Pubkey(pubkey) = pubkey;
+        let ClientSession { inv, prefix } = session;
+        let BlindSig(s) = server_msg;
+        let k = modulus_len(pubkey);
+        if s.len() != k {
+            return Err(Error::InvalidEncoding(format!(
+                "blind signature must be {} bytes",
+                k
+            )));
+        }
+
+        // σ := s · r⁻¹ mod n
+        let s = BigUint::from_bytes_be(s);
+        let σ = (s * inv) % pubkey.n();
+        let sig = Signature {
+            prefix,
+            s: i2osp(&σ, k).ok_or(Error::VerificationFailed)?,
+        };
+
+        // Check the unblinded signature
+        if rsassa_pss_verify(pubkey, &prepare(&prefix, m), &sig.s) {
+            Ok(ClientStep::Done(sig))
+        } else {
+            Err(Error::VerificationFailed)
+        }
+    }
+
+    fn server_step<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        privkey: &Privkey,
+        _pubkey: &Pubkey,
+        session: Option<()>,
+        client_msg: &BlindedMsg,
+    ) -> Result<ServerStep<Self>, Error> {
+        // There's only one move, so there should be no existing session
+        if session.is_some() {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        let Privkey(privkey) = privkey;
+        let BlindedMsg(z) = client_msg;
+        let k = modulus_len(privkey);
+        if z.len() != k {
+            return Err(Error::InvalidEncoding(format!(
+                "blinded message must be {} bytes",
+                k
+            )));
+        }
+
+        // s := zᵈ mod n. The client picks z, so the exponentiation is blinded, or its timing could
+        // give away d. Check that sᵉ = z, so we don't leak anything if there's a fault.
+        let z = BigUint::from_bytes_be(z);
+        let s = internals::decrypt(Some(rng), privkey, &z)
+            .map_err(|_| Error::InvalidEncoding("blinded message is out of range".to_string()))?;
+        if internals::encrypt(privkey, &s) != z {
+            return Err(Error::VerificationFailed);
+        }
+
+        // s < n, so this always fits in k bytes
+        Ok(ServerStep::Done(BlindSig(i2osp(&s, k).unwrap())))
+    }
+}
+
+#[cfg(test)]
+fn run_protocol<S: BlindSigProtocol>(
+    privkey: &S::Privkey,
+    pubkey: &S::Pubkey,
+    m: &[u8],
+) -> S::Signature {
+    let mut csprng = rand::thread_rng();
+
+    let (client_session, client_msg) = S::client_start(&mut csprng, pubkey, m);
+    let server_msg = match S::server_step(&mut csprng, privkey, pubkey, None, &client_msg) {
+        Ok(ServerStep::Done(msg)) => msg,
+        _ => panic!("server didn't finish in one move"),
+    };
+    match S::client_step(&mut csprng, pubkey, m, client_session, &server_msg) {
+        Ok(ClientStep::Done(sig)) => sig,
+        _ => panic!("client didn't finish in one move"),
+    }
+}
+
+#[test]
+fn test_correctness() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+
+    // Both variants can share a key
+    let (privkey, pubkey) = BlindRsaRandomized::keygen(&mut csprng);
+
+    let sig = run_protocol::<BlindRsaRandomized>(&privkey, &pubkey, m);
+    assert!(BlindRsaRandomized::verify(&pubkey, m, &sig));
+    assert!(!BlindRsaRandomized::verify(&pubkey, b"Goodbye world", &sig));
+    assert!(!BlindRsaDeterministic::verify(&pubkey, m, &sig));
+
+    let sig = run_protocol::<BlindRsaDeterministic>(&privkey, &pubkey, m);
+    assert!(BlindRsaDeterministic::verify(&pubkey, m, &sig));
+    assert!(!BlindRsaRandomized::verify(&pubkey, m, &sig));
+}
+
+// The client only unblinds a signature that's exactly as long as the modulus
+#[test]
+fn test_blind_sig_length() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    type S = BlindRsaRandomized;
+
+    let (privkey, pubkey) = S::keygen(&mut csprng);
+    let (_, client_msg) = S::client_start(&mut csprng, &pubkey, m);
+    let BlindSig(s) = match S::server_step(&mut csprng, &privkey, &pubkey, None, &client_msg) {
+        Ok(ServerStep::Done(msg)) => msg,
+        _ => panic!("server didn't finish in one move"),
+    };
+
+    let mut padded = vec![0u8];
+    padded.extend_from_slice(&s);
+    for bad_sig in [s[1..].to_vec(), padded] {
+        let (client_session, _) = S::client_start(&mut csprng, &pubkey, m);
+        let res = S::client_step(&mut csprng, &pubkey, m, client_session, &BlindSig(bad_sig));
+        assert!(matches!(res, Err(Error::InvalidEncoding(_))));
+    }
+}
+
+// The unblinded signatures are plain RSASSA-PSS signatures, so an off-the-shelf verifier should
+// accept them
+#[test]
+fn test_pss_compatibility() {
+    use rsa::{PaddingScheme, PublicKey};
+
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+
+    let (privkey, pubkey) = BlindRsaDeterministic::keygen(&mut csprng);
+    let sig = run_protocol::<BlindRsaDeterministic>(&privkey, &pubkey, m);
+
+    let hashed = Sha384::digest(m);
+    let padding = PaddingScheme::new_pss::<Sha384, _>(csprng);
+    assert!(pubkey.0.verify(padding, &hashed, &sig.s).is_ok());
+}
@@ -0,0 +1,221 @@
+/* The Clause Blind Schnorr signature scheme, using notation from
+*  https://eprint.iacr.org/2019/877.pdf
+*
+* This is two Blind Schnorr sessions run side by side, where the signer only finishes one of them,
+* chosen at random. This defeats the ROS attack on concurrent sessions, and the resulting
+* signatures are ordinary Schnorr signatures.
+*
+* x is the secret key
+* X is the public key
+* G is a group generator
+* S is the set of scalars
+* m is the message
+*
+*         Signer(x)                            Client(X, m)
+*         ----------                           ------------
+* Pick two nonces and commit to them
+*
+* r₀, r₁ ← S
+* R₀ := r₀G
+* R₁ := r₁G
+*
+*                              R₀, R₁
+*                              ----->
+*                                              Blind the commitments and
+*                                              make two blinded challenges
+*
+*                                              for i ∈ {0, 1}:
+*                                                αᵢ, βᵢ ← S
+*                                                Rᵢ' := Rᵢ + αᵢG + βᵢX
+*                                                cᵢ' := H(Rᵢ', m)
+*                                                cᵢ := cᵢ' + βᵢ
+*                              c₀, c₁
+*                              <-----
+* Pick one of the challenges
+* and respond to it
+*
+* b ← {0, 1}
+* s := r_b + c_b x
+*                               b, s
+*                              ----->
+*                                              Check validity, then undo
+*                                              blinding
+*
+*                                              if sG ≠ R_b + c_b X: abort
+*                                              s' := s + α_b
+*                                              σ := (R_b', s')
+*
+* KeyGen() and Verif() are exactly those of Blind Schnorr
+*/
+
+use crate::{
+    common::{FourMoveBlindSig, GroupElem, Scalar},
+    error::Error,
+    schnorr::{BlindSchnorr, Privkey, Pubkey, Signature},
+};
+
+use blake2::{digest::Digest, Blake2b};
+use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_TABLE, scalar::Scalar as ScalarRepr};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+pub struct ServerState {
+    r: [Scalar; 2],
+    // The signer's coin. This is picked in advance, since sign2 takes no randomness, but it isn't
+    // revealed until the signer responds to the challenge.
+    b: bool,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct ServerResp1 {
+    R: [GroupElem; 2],
+}
+
+#[derive(Clone)]
+pub struct ClientState {
+    α: [Scalar; 2],
+    c: [Scalar; 2],
+    R: [GroupElem; 2],
+    R_prime: [GroupElem; 2],
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct ClientResp {
+    c: [Scalar; 2],
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct ServerResp2 {
+    b: bool,
+    s: Scalar,
+}
+
+/// The impl of the Clause Blind Schnorr signature scheme
+pub struct ClauseBlindSchnorr;
+
+impl FourMoveBlindSig for ClauseBlindSchnorr {
+    // Clause Blind Schnorr is secure under polynomially many concurrent sessions (in the AGM, under
+    // the OMDL assumption and the hardness of modified ROS)
+    const MAX_PARALLEL_SESSIONS: usize = usize::MAX;
+
+    type Privkey = Privkey;
+    type Pubkey = Pubkey;
+
+    type ServerState = ServerState;
+    type ClientState = ClientState;
+    type ClientResp = ClientResp;
+    type ServerResp1 = ServerResp1;
+    type ServerResp2 = ServerResp2;
+    type Signature = Signature;
+
+    /// Generates a Schnorr keypair
+    fn keygen<R: RngCore + CryptoRng>(rng: &mut R) -> (Privkey, Pubkey) {
+        BlindSchnorr::keygen(rng)
+    }
+
+    /// Verifies the signature. This is just a Schnorr signature.
+    fn verify(pubkey: &Pubkey, m: &[u8], sig: &Signature) -> bool {
+        BlindSchnorr::verify(pubkey, m, sig)
+    }
+
+    fn sign1<R: RngCore + CryptoRng>(rng: &mut R, _pubkey: &Pubkey) -> (ServerState, ServerResp1) {
+        // r₀, r₁ ← S, R₀ := r₀G, R₁ := r₁G
+        let (r0, R0) = BlindSchnorr::keygen(rng);
+        let (r1, R1) = BlindSchnorr::keygen(rng);
+
+        // b ← {0, 1}
+        let b = rng.next_u32() & 1 == 1;
+
+        let state = ServerState { r: [r0.0, r1.0], b };
+        let resp = ServerResp1 { R: [R0.0, R1.0] };
+
+        (state, resp)
+    }
+
+    fn user1<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Pubkey,
+        m: &[u8],
+        server_resp1: &ServerResp1,
+    ) -> (ClientState, ClientResp) {
+        let Pubkey(X) = pubkey;
+        let &ServerResp1 { R } = server_resp1;
+
+        // Generate the blinding factors
+        let α = [Scalar::random(rng), Scalar::random(rng)];
+        let β = [Scalar::random(rng), Scalar::random(rng)];
+
+        // Blind both commitments
+        let blind = |i: usize| {
+            let αG = &α[i].0 * &RISTRETTO_BASEPOINT_TABLE;
+            let βX = β[i].0 * X.0;
+            GroupElem(R[i].0 + αG + βX)
+        };
+        let R_prime = [blind(0), blind(1)];
+
+        // Compute the hashes cᵢ' = H(Rᵢ', m), and from them the cᵢ
+        let challenge = |i: usize| {
+            let c_prime: ScalarRepr =
+                ScalarRepr::from_hash(Blake2b::default().chain(R_prime[i].to_bytes()).chain(m));
+            Scalar(c_prime + β[i].0)
+        };
+        let c = [challenge(0), challenge(1)];
+
+        let state = ClientState { α, c, R, R_prime };
+        let resp = ClientResp { c };
+
+        (state, resp)
+    }
+
+    fn sign2(privkey: &Privkey, state: ServerState, client_resp: &ClientResp) -> ServerResp2 {
+        let Privkey(x) = privkey;
+        let ServerState { r, b } = state;
+        let ClientResp { c } = client_resp;
+
+        // Only ever answer one of the two challenges
+        let i = b as usize;
+        let s = Scalar(r[i].0 + c[i].0 * x.0);
+
+        ServerResp2 { b, s }
+    }
+
+    fn user2(
+        pubkey: &Pubkey,
+        state: &ClientState,
+        _m: &[u8],
+        server_resp2: &ServerResp2,
+    ) -> Result<Signature, Error> {
+        let Pubkey(X) = pubkey;
+        let ClientState { α, c, R, R_prime } = state;
+        let &ServerResp2 { b, s } = server_resp2;
+        let i = b as usize;
+
+        // Check sG == R_b + c_b X
+        let sG = &s.0 * &RISTRETTO_BASEPOINT_TABLE;
+        if sG != R[i].0 + c[i].0 * X.0 {
+            return Err(Error::VerificationFailed);
+        }
+
+        let s_prime = Scalar(s.0 + α[i].0);
+
+        Ok(Signature {
+            R_prime: R_prime[i],
+            s_prime,
+        })
+    }
+}
+
+#[test]
+fn test_correctness() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    type Alg = ClauseBlindSchnorr;
+
+    let (privkey, pubkey) = Alg::keygen(&mut csprng);
+    let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
+    let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
+    let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
+    let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
+
+    assert!(Alg::verify(&pubkey, m, &sig));
+}
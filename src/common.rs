@@ -1,9 +1,11 @@
+use crate::error::Error;
+
 use curve25519_dalek::{
     ristretto::{CompressedRistretto, RistrettoPoint},
     scalar::Scalar as ScalarRepr,
 };
 use rand::{CryptoRng, RngCore};
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 
 fn serialize_ristretto_point<S: Serializer>(
     point: &RistrettoPoint,
@@ -19,10 +21,9 @@ fn deserialize_ristretto_point<'de, D: Deserializer<'de>>(
     let bytes = <[u8; 32]>::deserialize(de)?;
     let compressed = CompressedRistretto::from_slice(&bytes);
 
-    let point = compressed
+    compressed
         .decompress()
-        .expect("encountered an invalid Ristretto point");
-    Ok(point)
+        .ok_or_else(|| D::Error::custom("encountered an invalid Ristretto point"))
 }
 
 #[derive(Copy, Clone, Deserialize, Serialize)]
@@ -44,6 +45,15 @@ impl GroupElem {
     pub fn to_bytes(&self) -> [u8; 32] {
         self.0.compress().to_bytes()
     }
+
+    /// Decodes a compressed Ristretto point. Errors if it isn't a valid encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<GroupElem, Error> {
+        let [bytes] = split_encoding::<1>(bytes, "group element")?;
+        CompressedRistretto(bytes)
+            .decompress()
+            .map(GroupElem)
+            .ok_or_else(|| Error::InvalidEncoding("invalid Ristretto point".to_string()))
+    }
 }
 
 fn serialize_scalar<S: Serializer>(scalar: &ScalarRepr, ser: S) -> Result<S::Ok, S::Error> {
@@ -52,8 +62,8 @@ fn serialize_scalar<S: Serializer>(scalar: &ScalarRepr, ser: S) -> Result<S::Ok,
 
 fn deserialize_scalar<'de, D: Deserializer<'de>>(de: D) -> Result<ScalarRepr, D::Error> {
     let bytes = <[u8; 32]>::deserialize(de)?;
-    let scalar = ScalarRepr::from_canonical_bytes(bytes).expect("encountered an invalid scalar");
-    Ok(scalar)
+    ScalarRepr::from_canonical_bytes(bytes)
+        .ok_or_else(|| D::Error::custom("encountered an invalid scalar"))
 }
 
 #[derive(Copy, Clone, Default, Deserialize, Serialize)]
@@ -75,6 +85,51 @@ impl Scalar {
     pub fn random<R: CryptoRng + RngCore>(rng: &mut R) -> Scalar {
         Scalar(ScalarRepr::random(rng))
     }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// Decodes a little-endian scalar. Errors if it isn't reduced mod the group order.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Scalar, Error> {
+        let [bytes] = split_encoding::<1>(bytes, "scalar")?;
+        ScalarRepr::from_canonical_bytes(bytes)
+            .map(Scalar)
+            .ok_or_else(|| Error::InvalidEncoding("non-canonical scalar".to_string()))
+    }
+}
+
+/// Splits the encoding of `N` 32-byte values into its parts. Errors if it's the wrong length.
+pub(crate) fn split_encoding<const N: usize>(
+    bytes: &[u8],
+    what: &str,
+) -> Result<[[u8; 32]; N], Error> {
+    if bytes.len() != 32 * N {
+        return Err(Error::InvalidEncoding(format!(
+            "{} must be {} bytes, got {}",
+            what,
+            32 * N,
+            bytes.len()
+        )));
+    }
+
+    let mut parts = [[0u8; 32]; N];
+    for (part, chunk) in parts.iter_mut().zip(bytes.chunks_exact(32)) {
+        part.copy_from_slice(chunk);
+    }
+    Ok(parts)
+}
+
+/// Concatenates 32-byte values into a single `M`-byte encoding. `M` must be 32 times the number
+/// of parts.
+pub(crate) fn join_encoding<const M: usize>(parts: &[[u8; 32]]) -> [u8; M] {
+    assert_eq!(M, 32 * parts.len());
+
+    let mut bytes = [0u8; M];
+    for (chunk, part) in bytes.chunks_exact_mut(32).zip(parts) {
+        chunk.copy_from_slice(part);
+    }
+    bytes
 }
 
 pub trait FourMoveBlindSig {
@@ -83,12 +138,14 @@ pub trait FourMoveBlindSig {
     type Privkey: Clone + Copy + Send + Sync + 'static;
     type Pubkey: Clone + Copy + Send + Sync + 'static;
 
-    type ServerState: Clone + Send + Sync + 'static;
-    type ClientState: Clone;
-    type ClientResp: Clone + for<'de> Deserialize<'de> + Serialize;
-    type ServerResp1: Clone + for<'de> Deserialize<'de> + Serialize;
-    type ServerResp2: Clone + for<'de> Deserialize<'de> + Serialize;
-    type Signature: Clone;
+    /// The signer's state between its two moves, i.e., its nonces. Answering two challenges with
+    /// the same nonce leaks the private key, so this shouldn't be Clone.
+    type ServerState: Send + Sync + 'static;
+    type ClientState: Clone + Send + 'static;
+    type ClientResp: Clone + Send + 'static + for<'de> Deserialize<'de> + Serialize;
+    type ServerResp1: Clone + Send + 'static + for<'de> Deserialize<'de> + Serialize;
+    type ServerResp2: Clone + Send + 'static + for<'de> Deserialize<'de> + Serialize;
+    type Signature: Clone + Send + 'static;
 
     fn keygen<R: RngCore + CryptoRng>(rng: &mut R) -> (Self::Privkey, Self::Pubkey);
     fn verify(pubkey: &Self::Pubkey, m: &[u8], sig: &Self::Signature) -> bool;
@@ -103,9 +160,11 @@ pub trait FourMoveBlindSig {
         m: &[u8],
         server_resp1: &Self::ServerResp1,
     ) -> (Self::ClientState, Self::ClientResp);
+    /// Answers the client's challenge. This consumes the state, so each nonce is only ever used
+    /// once.
     fn sign2(
         privkey: &Self::Privkey,
-        state: &Self::ServerState,
+        state: Self::ServerState,
         client_resp: &Self::ClientResp,
     ) -> Self::ServerResp2;
     fn user2(
@@ -113,5 +172,230 @@ pub trait FourMoveBlindSig {
         state: &Self::ClientState,
         m: &[u8],
         server_resp2: &Self::ServerResp2,
-    ) -> Option<Self::Signature>;
+    ) -> Result<Self::Signature, Error>;
+}
+
+/// What the client does after it receives a message from the server
+pub enum ClientStep<P: BlindSigProtocol + ?Sized> {
+    /// The session goes on. This is the client's new state and its next message to the server.
+    Continue(P::ClientSession, P::ClientMsg),
+    /// The session is over and the client has its signature
+    Done(P::Signature),
+}
+
+/// What the server does after it receives a message from the client
+pub enum ServerStep<P: BlindSigProtocol + ?Sized> {
+    /// The session goes on. This is the server's new state and its response to the client.
+    Continue(P::ServerSession, P::ServerMsg),
+    /// The session is over. This is the server's final response to the client.
+    Done(P::ServerMsg),
+}
+
+/// A blind signature protocol with any number of moves, written as a pair of state machines. The
+/// client always speaks first, and the server always responds. Each side consumes its current
+/// state and the other side's message, and produces its next state and message, until the client
+/// ends up with a signature.
+pub trait BlindSigProtocol {
+    const MAX_PARALLEL_SESSIONS: usize;
+
+    type Privkey: Clone + Send + Sync + 'static;
+    type Pubkey: Clone + Send + Sync + 'static;
+
+    type ServerSession: Send + Sync + 'static;
+    type ClientSession: Send + 'static;
+    type ClientMsg: Send + 'static + for<'de> Deserialize<'de> + Serialize;
+    type ServerMsg: Send + 'static + for<'de> Deserialize<'de> + Serialize;
+    type Signature: Clone + Send + 'static;
+
+    fn keygen<R: RngCore + CryptoRng>(rng: &mut R) -> (Self::Privkey, Self::Pubkey);
+    fn verify(pubkey: &Self::Pubkey, m: &[u8], sig: &Self::Signature) -> bool;
+
+    /// Starts a session on the client side. Returns the client's state and its first message.
+    fn client_start<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Self::Pubkey,
+        m: &[u8],
+    ) -> (Self::ClientSession, Self::ClientMsg);
+
+    /// Processes a message from the server. Returns an error if the server misbehaved.
+    fn client_step<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Self::Pubkey,
+        m: &[u8],
+        session: Self::ClientSession,
+        server_msg: &Self::ServerMsg,
+    ) -> Result<ClientStep<Self>, Error>;
+
+    /// Returns whether the given message can be the first of a session. The server checks this
+    /// before giving a new session a slot. In a protocol where the client only speaks once, every
+    /// message can.
+    fn opens_session(_client_msg: &Self::ClientMsg) -> bool {
+        true
+    }
+
+    /// Processes a message from the client. `session` is `None` iff this is the first message of
+    /// the session. Returns an error if the message isn't valid at this point in the protocol.
+    fn server_step<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        privkey: &Self::Privkey,
+        pubkey: &Self::Pubkey,
+        session: Option<Self::ServerSession>,
+        client_msg: &Self::ClientMsg,
+    ) -> Result<ServerStep<Self>, Error>;
+}
+
+/// The messages a client sends in a `FourMoveBlindSig` protocol
+#[derive(Deserialize, Serialize)]
+#[serde(bound = "")]
+pub enum FourMoveClientMsg<S: FourMoveBlindSig> {
+    /// Asks the server to start a session. This carries no data.
+    Start,
+    ClientResp(S::ClientResp),
+}
+
+/// The messages a server sends in a `FourMoveBlindSig` protocol
+#[derive(Deserialize, Serialize)]
+#[serde(bound = "")]
+pub enum FourMoveServerMsg<S: FourMoveBlindSig> {
+    ServerResp1(S::ServerResp1),
+    ServerResp2(S::ServerResp2),
+}
+
+/// The client's state in a `FourMoveBlindSig` protocol
+pub enum FourMoveClientSession<S: FourMoveBlindSig> {
+    /// Waiting on the server's commitment
+    AwaitingResp1,
+    /// Waiting on the server's response to the challenge
+    AwaitingResp2(S::ClientState),
+}
+
+// A four-move protocol is a protocol where the client sends an empty message to kick things off,
+// and then the steps happen in order
+impl<S: FourMoveBlindSig + 'static> BlindSigProtocol for S {
+    const MAX_PARALLEL_SESSIONS: usize = <S as FourMoveBlindSig>::MAX_PARALLEL_SESSIONS;
+
+    type Privkey = <S as FourMoveBlindSig>::Privkey;
+    type Pubkey = <S as FourMoveBlindSig>::Pubkey;
+
+    type ServerSession = S::ServerState;
+    type ClientSession = FourMoveClientSession<S>;
+    type ClientMsg = FourMoveClientMsg<S>;
+    type ServerMsg = FourMoveServerMsg<S>;
+    type Signature = <S as FourMoveBlindSig>::Signature;
+
+    fn keygen<R: RngCore + CryptoRng>(rng: &mut R) -> (Self::Privkey, Self::Pubkey) {
+        <S as FourMoveBlindSig>::keygen(rng)
+    }
+
+    fn verify(pubkey: &Self::Pubkey, m: &[u8], sig: &Self::Signature) -> bool {
+        <S as FourMoveBlindSig>::verify(pubkey, m, sig)
+    }
+
+    fn client_start<R: RngCore + CryptoRng>(
+        _rng: &mut R,
+        _pubkey: &Self::Pubkey,
+        _m: &[u8],
+    ) -> (Self::ClientSession, Self::ClientMsg) {
+        (
+            FourMoveClientSession::AwaitingResp1,
+            FourMoveClientMsg::Start,
+        )
+    }
+
+    fn client_step<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Self::Pubkey,
+        m: &[u8],
+        session: Self::ClientSession,
+        server_msg: &Self::ServerMsg,
+    ) -> Result<ClientStep<Self>, Error> {
+        match (session, server_msg) {
+            (FourMoveClientSession::AwaitingResp1, FourMoveServerMsg::ServerResp1(resp)) => {
+                let (state, client_resp) = S::user1(rng, pubkey, m, resp);
+                Ok(ClientStep::Continue(
+                    FourMoveClientSession::AwaitingResp2(state),
+                    FourMoveClientMsg::ClientResp(client_resp),
+                ))
+            }
+            (FourMoveClientSession::AwaitingResp2(state), FourMoveServerMsg::ServerResp2(resp)) => {
+                S::user2(pubkey, &state, m, resp).map(ClientStep::Done)
+            }
+            _ => Err(Error::UnexpectedMessage),
+        }
+    }
+
+    fn opens_session(client_msg: &Self::ClientMsg) -> bool {
+        matches!(client_msg, FourMoveClientMsg::Start)
+    }
+
+    fn server_step<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        privkey: &Self::Privkey,
+        pubkey: &Self::Pubkey,
+        session: Option<Self::ServerSession>,
+        client_msg: &Self::ClientMsg,
+    ) -> Result<ServerStep<Self>, Error> {
+        match (session, client_msg) {
+            (None, FourMoveClientMsg::Start) => {
+                let (state, resp) = S::sign1(rng, pubkey);
+                Ok(ServerStep::Continue(
+                    state,
+                    FourMoveServerMsg::ServerResp1(resp),
+                ))
+            }
+            (Some(state), FourMoveClientMsg::ClientResp(client_resp)) => {
+                let resp = S::sign2(privkey, state, client_resp);
+                Ok(ServerStep::Done(FourMoveServerMsg::ServerResp2(resp)))
+            }
+            (None, _) => Err(Error::UnknownSession),
+            (Some(_), _) => Err(Error::UnexpectedMessage),
+        }
+    }
+}
+
+#[test]
+fn test_four_move_adapter() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    type Alg = crate::abe::Abe;
+
+    let (privkey, pubkey) = <Alg as BlindSigProtocol>::keygen(&mut csprng);
+    let (mut client_session, mut client_msg) = Alg::client_start(&mut csprng, &pubkey, m);
+    let mut server_session = None;
+
+    let sig = loop {
+        let server_msg = match Alg::server_step(
+            &mut csprng,
+            &privkey,
+            &pubkey,
+            server_session.take(),
+            &client_msg,
+        )
+        .unwrap()
+        {
+            ServerStep::Continue(session, msg) => {
+                server_session = Some(session);
+                msg
+            }
+            ServerStep::Done(msg) => msg,
+        };
+
+        match Alg::client_step(&mut csprng, &pubkey, m, client_session, &server_msg).unwrap() {
+            ClientStep::Continue(session, msg) => {
+                client_session = session;
+                client_msg = msg;
+            }
+            ClientStep::Done(sig) => break sig,
+        }
+    };
+    assert!(<Alg as BlindSigProtocol>::verify(&pubkey, m, &sig));
+
+    // A server that's never seen this client shouldn't accept a challenge from it
+    let (_, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
+    let (_, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
+    let client_msg = FourMoveClientMsg::<Alg>::ClientResp(client_resp);
+    assert_eq!(
+        Alg::server_step(&mut csprng, &privkey, &pubkey, None, &client_msg).err(),
+        Some(Error::UnknownSession)
+    );
 }
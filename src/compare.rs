@@ -0,0 +1,165 @@
+/*
+Comparing two sets of webserver benchmark results, e.g., the EC2 data and a fresh run in
+target/criterion. Benchmarks are matched up by scheme, pool size, number of clients, and
+interarrival time. Anything that only one side has is reported but not compared.
+
+A change counts as significant if the two confidence intervals on the mean don't overlap. This is
+conservative, but it's all we can do with the estimates Criterion keeps, and it's the same thing
+you'd eyeball from the error bars on the plots.
+*/
+
+use crate::results::{discover, BenchId, Estimates};
+use std::{error::Error, path::Path};
+
+/// The same benchmark, run twice
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub id: BenchId,
+    pub baseline: Estimates,
+    pub candidate: Estimates,
+}
+
+impl Comparison {
+    /// How much longer the candidate took than the baseline, as a fraction of the baseline. This is
+    /// negative if the candidate is faster.
+    pub fn relative_change(&self) -> f64 {
+        self.candidate.mean / self.baseline.mean - 1.0
+    }
+
+    /// The smallest and largest relative change that fit both confidence intervals. If the
+    /// baseline's interval reaches down to 0, there's no largest change.
+    pub fn change_interval(&self) -> (f64, f64) {
+        let upper = if self.baseline.mean_lower > 0.0 {
+            self.candidate.mean_upper / self.baseline.mean_lower - 1.0
+        } else {
+            f64::INFINITY
+        };
+        (
+            self.candidate.mean_lower / self.baseline.mean_upper - 1.0,
+            upper,
+        )
+    }
+
+    /// Whether the confidence intervals are disjoint, i.e., whether the change interval excludes 0
+    pub fn is_significant(&self) -> bool {
+        let (lower, upper) = self.change_interval();
+        lower > 0.0 || upper < 0.0
+    }
+
+    /// Whether the candidate is significantly slower than the baseline, by more than the given
+    /// fraction of the baseline
+    pub fn is_regression(&self, threshold: f64) -> bool {
+        self.is_significant() && self.relative_change() > threshold
+    }
+}
+
+/// Everything found in two Criterion output directories, matched up
+#[derive(Clone, Debug, Default)]
+pub struct ComparisonSet {
+    /// Benchmarks that both directories have. These are in the order `results::discover` returns.
+    pub comparisons: Vec<Comparison>,
+    /// Benchmarks that only the baseline has
+    pub only_baseline: Vec<BenchId>,
+    /// Benchmarks that only the candidate has
+    pub only_candidate: Vec<BenchId>,
+}
+
+impl ComparisonSet {
+    /// Returns every comparison that's a regression past the given threshold
+    pub fn regressions(&self, threshold: f64) -> impl Iterator<Item = &Comparison> {
+        self.comparisons
+            .iter()
+            .filter(move |c| c.is_regression(threshold))
+    }
+}
+
+/// Finds every webserver benchmark result in both Criterion output directories, and matches them up
+pub fn compare_dirs<P: AsRef<Path>, Q: AsRef<Path>>(
+    baseline_dir: P,
+    candidate_dir: Q,
+) -> Result<ComparisonSet, Box<dyn Error>> {
+    let baseline = discover(baseline_dir)?;
+    let mut candidate = discover(candidate_dir)?;
+    let mut set = ComparisonSet::default();
+
+    for base in baseline {
+        match candidate.iter().position(|cand| cand.id == base.id) {
+            Some(i) => {
+                let cand = candidate.remove(i);
+                set.comparisons.push(Comparison {
+                    baseline: base.estimates()?,
+                    candidate: cand.estimates()?,
+                    id: base.id,
+                });
+            }
+            None => set.only_baseline.push(base.id),
+        }
+    }
+    set.only_candidate = candidate.into_iter().map(|cand| cand.id).collect();
+
+    Ok(set)
+}
+
+// Nothing changes between a set of results and itself
+#[test]
+fn test_compare_to_self() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/ec2_data/webserver_bench");
+    let set = compare_dirs(dir, dir).unwrap();
+
+    assert_eq!(set.comparisons.len(), 4 * 5);
+    assert!(set.only_baseline.is_empty() && set.only_candidate.is_empty());
+    for c in &set.comparisons {
+        assert_eq!(c.relative_change(), 0.0);
+        assert!(!c.is_significant());
+    }
+    assert_eq!(set.regressions(0.0).count(), 0);
+
+    // An empty directory has nothing in common with the EC2 data
+    let empty =
+        std::env::temp_dir().join(format!("blind_sig_bench_compare_{}", std::process::id()));
+    std::fs::create_dir_all(&empty).unwrap();
+    let set = compare_dirs(dir, &empty).unwrap();
+    assert!(set.comparisons.is_empty());
+    assert_eq!(set.only_baseline.len(), 4 * 5);
+    std::fs::remove_dir_all(empty).unwrap();
+}
+
+#[test]
+fn test_regression() {
+    let estimates = |mean: f64| Estimates {
+        mean,
+        mean_lower: mean - 1.0,
+        mean_upper: mean + 1.0,
+        confidence_level: 0.95,
+        std_dev: 1.0,
+    };
+    let id = BenchId::parse(
+        "Parallel Abe",
+        "4-core server handling 100 clients at 10ms EIAT",
+    )
+    .unwrap();
+    let compare = |baseline, candidate| Comparison {
+        id: id.clone(),
+        baseline: estimates(baseline),
+        candidate: estimates(candidate),
+    };
+
+    // Overlapping intervals aren't significant, however big the change in the mean
+    let c = compare(10.0, 11.5);
+    assert!((c.relative_change() - 0.15).abs() < 1e-9);
+    assert!(!c.is_significant() && !c.is_regression(0.0));
+
+    // 20% slower is a regression past 10%, but not past 25%
+    let c = compare(10.0, 12.0 + 1e-9);
+    assert!(c.is_significant());
+    assert!(c.is_regression(0.1) && !c.is_regression(0.25));
+
+    // Getting faster is never a regression
+    let c = compare(12.0, 9.0);
+    assert!(c.is_significant() && !c.is_regression(0.0));
+
+    // A baseline whose interval takes in 0 could be any amount faster than the candidate
+    let c = compare(0.5, 0.4);
+    assert_eq!(c.change_interval().1, f64::INFINITY);
+    assert!(!c.is_significant());
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// Everything that can go wrong in a signing session, on either side
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Some bytes weren't a valid encoding of what they were supposed to be
+    InvalidEncoding(String),
+    /// The server has no session for this client
+    UnknownSession,
+    /// The message isn't valid at this point in the session
+    UnexpectedMessage,
+    /// The server is already running as many sessions as the scheme allows
+    CapacityExceeded,
+    /// A signature, or a response from the signer, didn't verify
+    VerificationFailed,
+    /// The other party couldn't be reached at all
+    ConnectionFailed(String),
+    /// Something went wrong talking to the other party
+    Transport(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidEncoding(what) => write!(f, "invalid encoding: {}", what),
+            Error::UnknownSession => write!(f, "unknown session"),
+            Error::UnexpectedMessage => write!(f, "unexpected message"),
+            Error::CapacityExceeded => write!(f, "too many concurrent sessions"),
+            Error::VerificationFailed => write!(f, "verification failed"),
+            Error::ConnectionFailed(what) => write!(f, "connection failed: {}", what),
+            Error::Transport(what) => write!(f, "transport error: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,274 @@
+/*
+A flat record of a benchmark run, one row per scheme × pool size × interarrival time. Every row
+carries everything needed to interpret it on its own: the machine it ran on, the git revision, the
+scenario parameters, and the summary statistics. Result files from different runs and machines can
+be merged by just concatenating their rows.
+*/
+
+use crate::{
+    results::Estimates,
+    scenario::{ArrivalProcess, Scenario, Scheme, ServerBackend},
+};
+use std::{
+    error::Error,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The environment variable that says where to write results. The CSV goes to this path with a
+/// .csv extension, and the JSON with a .json extension.
+pub const RESULTS_VAR: &str = "BENCH_RESULTS";
+
+/// Where Criterion puts its output. This follows Criterion's own rules, except that it doesn't ask
+/// cargo where the target directory is.
+pub fn criterion_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("CRITERION_HOME") {
+        PathBuf::from(dir)
+    } else if let Some(dir) = std::env::var_os("CARGO_TARGET_DIR") {
+        PathBuf::from(dir).join("criterion")
+    } else {
+        PathBuf::from("target/criterion")
+    }
+}
+
+/// Where to write results, minus the extension. This is `BENCH_RESULTS` if it's set, and
+/// results in the Criterion directory otherwise.
+pub fn results_path() -> PathBuf {
+    match std::env::var_os(RESULTS_VAR) {
+        Some(path) => PathBuf::from(path),
+        None => criterion_dir().join("results"),
+    }
+}
+
+/// What machine and code a run happened on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub cpu_model: String,
+    /// Number of logical cores
+    pub num_cores: usize,
+    pub os: String,
+    pub arch: String,
+    /// The commit that was checked out, with "-dirty" on the end if there were uncommitted changes
+    pub git_revision: String,
+    /// When the run started, in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl RunMetadata {
+    /// Looks up everything about this machine and checkout. Anything that can't be found out is
+    /// "unknown".
+    pub fn collect() -> RunMetadata {
+        RunMetadata {
+            cpu_model: cpu_model().unwrap_or_else(|| "unknown".to_string()),
+            num_cores: std::thread::available_parallelism().map_or(0, |n| n.get()),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            git_revision: git_revision().unwrap_or_else(|| "unknown".to_string()),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+}
+
+/// Returns the CPU model name. This only knows how to ask Linux and macOS.
+fn cpu_model() -> Option<String> {
+    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+        return cpuinfo
+            .lines()
+            .find(|line| line.starts_with("model name"))
+            .and_then(|line| line.split_once(':'))
+            .map(|(_, model)| model.trim().to_string());
+    }
+
+    let out = Command::new("sysctl")
+        .args(["-n", "machdep.cpu.brand_string"])
+        .output()
+        .ok()?;
+    let model = String::from_utf8(out.stdout).ok()?.trim().to_string();
+    Some(model).filter(|m| !m.is_empty())
+}
+
+/// Returns the hash of HEAD, marked dirty if the working tree has changes
+fn git_revision() -> Option<String> {
+    let git = |args: &[&str]| {
+        let out = Command::new("git").args(args).output().ok()?;
+        if out.status.success() {
+            String::from_utf8(out.stdout).ok()
+        } else {
+            None
+        }
+    };
+
+    let rev = git(&["rev-parse", "HEAD"])?.trim().to_string();
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.trim().is_empty());
+    Some(if dirty { rev + "-dirty" } else { rev })
+}
+
+/// One benchmark's results, along with everything about how it was run
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResultRow {
+    // The machine and code
+    pub cpu_model: String,
+    pub num_cores: usize,
+    pub os: String,
+    pub arch: String,
+    pub git_revision: String,
+    pub timestamp: u64,
+
+    // The benchmark
+    pub scheme: Scheme,
+    pub pool_size: usize,
+    pub num_clients: usize,
+    pub arrival_process: ArrivalProcess,
+    pub eiat_ms: f64,
+    /// The scenario's latency model, as JSON
+    pub latency_model: String,
+    pub session_timeout_s: u64,
+    /// The scenario's seed, if it had one
+    pub seed: Option<u64>,
+    /// "http" or "in-memory"
+    pub transport: String,
+    pub server_backend: ServerBackend,
+
+    // The results. Times are in nanoseconds.
+    pub mean_ns: f64,
+    pub mean_lower_ns: f64,
+    pub mean_upper_ns: f64,
+    pub confidence_level: f64,
+    pub std_dev_ns: f64,
+    /// Retries across every client in every iteration
+    pub client_retries: u64,
+    /// The fraction of the clients' time spent backing off
+    pub backoff_fraction: f64,
+}
+
+/// Client-side counts that Criterion doesn't know about
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClientTotals {
+    pub num_retries: u64,
+    pub backoff_fraction: f64,
+}
+
+impl ResultRow {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        metadata: &RunMetadata,
+        scenario: &Scenario,
+        scheme: Scheme,
+        pool_size: usize,
+        eiat_ms: f64,
+        transport: &str,
+        estimates: &Estimates,
+        client_totals: ClientTotals,
+    ) -> ResultRow {
+        ResultRow {
+            cpu_model: metadata.cpu_model.clone(),
+            num_cores: metadata.num_cores,
+            os: metadata.os.clone(),
+            arch: metadata.arch.clone(),
+            git_revision: metadata.git_revision.clone(),
+            timestamp: metadata.timestamp,
+
+            scheme,
+            pool_size,
+            num_clients: scenario.num_clients,
+            arrival_process: scenario.arrival_process,
+            eiat_ms,
+            // A LatencyModel can always be encoded
+            latency_model: serde_json::to_string(&scenario.latency).unwrap(),
+            session_timeout_s: scenario.session_timeout_s,
+            seed: scenario.seed,
+            transport: transport.to_string(),
+            server_backend: scenario.server_backend,
+
+            mean_ns: estimates.mean,
+            mean_lower_ns: estimates.mean_lower,
+            mean_upper_ns: estimates.mean_upper,
+            confidence_level: estimates.confidence_level,
+            std_dev_ns: estimates.std_dev,
+            client_retries: client_totals.num_retries,
+            backoff_fraction: client_totals.backoff_fraction,
+        }
+    }
+}
+
+/// Writes the rows as CSV, with a header, to the given path with a .csv extension, and as a JSON
+/// array to the given path with a .json extension. Returns the paths that were written.
+pub fn write_results<P: AsRef<Path>>(
+    path: P,
+    rows: &[ResultRow],
+) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+    let csv_path = path.as_ref().with_extension("csv");
+    let json_path = path.as_ref().with_extension("json");
+    if let Some(dir) = csv_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut writer = csv::Writer::from_path(&csv_path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+
+    serde_json::to_writer_pretty(File::create(&json_path)?, rows)?;
+
+    Ok((csv_path, json_path))
+}
+
+/// Reads the rows back out of a CSV results file
+pub fn read_csv<P: AsRef<Path>>(path: P) -> Result<Vec<ResultRow>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let rows = reader
+        .deserialize()
+        .collect::<Result<Vec<ResultRow>, _>>()?;
+    Ok(rows)
+}
+
+// Rows survive a trip through both formats, and the metadata is filled in
+#[test]
+fn test_write_results() {
+    let metadata = RunMetadata::collect();
+    assert!(metadata.num_cores > 0);
+    assert!(!metadata.cpu_model.is_empty());
+
+    let estimates = Estimates {
+        mean: 1.5e9,
+        mean_lower: 1.4e9,
+        mean_upper: 1.6e9,
+        confidence_level: 0.95,
+        std_dev: 2e8,
+    };
+    let scenario = Scenario::default();
+    let mut rows: Vec<ResultRow> = [(Scheme::Abe, 4), (Scheme::BlindSchnorr, 1)]
+        .iter()
+        .map(|&(scheme, pool_size)| {
+            ResultRow::new(
+                &metadata,
+                &scenario,
+                scheme,
+                pool_size,
+                10.0,
+                "http",
+                &estimates,
+                ClientTotals::default(),
+            )
+        })
+        .collect();
+    // Missing seeds come back as missing
+    rows[1].seed = Some(42);
+
+    let dir = std::env::temp_dir().join(format!("blind_sig_bench_export_{}", std::process::id()));
+    let (csv_path, json_path) = write_results(dir.join("results"), &rows).unwrap();
+    assert_eq!(read_csv(&csv_path).unwrap(), rows);
+    let from_json: Vec<ResultRow> =
+        serde_json::from_reader(File::open(&json_path).unwrap()).unwrap();
+    assert_eq!(from_json, rows);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,500 @@
+/*
+A model of the network between a client and the server. The delay is added on the client's side of
+the transport, so while a message is "in flight" it's the client's thread that waits, and the
+server's threads are free to work on other sessions. This is unlike the server-side latency sleep,
+which charges the delay to server capacity.
+
+Every message is delayed on the way to the server and again on the way back. Each one-way delay is
+made up of:
+
+    propagation   half the client's round-trip time, split between the directions by uplink_share
+    jitter        sampled per message, separately for each direction
+    transfer      message size / bandwidth, if the link has a bandwidth
+    retransmits   every time the message is lost, one retransmit timeout, doubling each time
+
+The round-trip time is fixed per client, or, if it comes from a trace, is the next sample in the
+trace for every round trip.
+*/
+
+use crate::{
+    error::Error,
+    transport::{Envelope, Transport},
+};
+use std::{
+    error::Error as StdError,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc, Mutex,
+    },
+    thread::sleep,
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+/// A distribution of delays, in milliseconds. Negative samples count as 0.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "distr", rename_all = "kebab-case")]
+pub enum Delay {
+    Constant { ms: f64 },
+    Normal { mean_ms: f64, std_ms: f64 },
+}
+
+impl Default for Delay {
+    fn default() -> Delay {
+        Delay::Constant { ms: 0.0 }
+    }
+}
+
+impl Delay {
+    /// Checks that the delay can be sampled: every number is finite, and the standard deviation
+    /// isn't negative
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            Delay::Constant { ms } if !ms.is_finite() => Err(format!("invalid delay {}ms", ms)),
+            Delay::Normal { mean_ms, std_ms }
+                if !mean_ms.is_finite() || !std_ms.is_finite() || *std_ms < 0.0 =>
+            {
+                Err(format!(
+                    "invalid delay distribution: mean {}ms, standard deviation {}ms",
+                    mean_ms, std_ms
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Samples a delay in milliseconds. The delay has to have passed `check`.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        let ms = match self {
+            Delay::Constant { ms } => *ms,
+            Delay::Normal { mean_ms, std_ms } => {
+                Normal::new(*mean_ms, *std_ms).unwrap().sample(rng)
+            }
+        };
+        ms.max(0.0)
+    }
+}
+
+/// Where a client's round-trip time comes from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "kebab-case")]
+pub enum RttSource {
+    /// Every client gets an RTT drawn from this distribution when it connects, and keeps it
+    PerClient(Delay),
+    /// Replays the RTTs in a CSV file, one per round trip. The RTT in milliseconds is the first
+    /// column, and a header line is skipped. Each client starts at a random point in the trace and
+    /// wraps around at the end.
+    Trace { path: PathBuf },
+}
+
+impl Default for RttSource {
+    fn default() -> RttSource {
+        RttSource::PerClient(Delay::default())
+    }
+}
+
+fn default_uplink_share() -> f64 {
+    0.5
+}
+
+fn default_retransmit_timeout_ms() -> f64 {
+    200.0
+}
+
+/// Everything that describes the network between clients and the server. Every field has a
+/// default, and the defaults add up to a network with no delay at all.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkModel {
+    #[serde(default)]
+    pub rtt: RttSource,
+    /// The fraction of the RTT spent on the way to the server
+    #[serde(default = "default_uplink_share")]
+    pub uplink_share: f64,
+    /// Extra delay on every message to the server
+    #[serde(default)]
+    pub uplink_jitter: Delay,
+    /// Extra delay on every message from the server
+    #[serde(default)]
+    pub downlink_jitter: Delay,
+    /// Bytes per second to the server. `None` means unlimited.
+    #[serde(default)]
+    pub uplink_bandwidth: Option<f64>,
+    /// Bytes per second from the server. `None` means unlimited.
+    #[serde(default)]
+    pub downlink_bandwidth: Option<f64>,
+    /// The probability that any single transmission of a message is lost
+    #[serde(default)]
+    pub loss_rate: f64,
+    /// How long a sender waits before retransmitting a lost message, the first time
+    #[serde(default = "default_retransmit_timeout_ms")]
+    pub retransmit_timeout_ms: f64,
+}
+
+impl Default for NetworkModel {
+    fn default() -> NetworkModel {
+        NetworkModel {
+            rtt: RttSource::default(),
+            uplink_share: default_uplink_share(),
+            uplink_jitter: Delay::default(),
+            downlink_jitter: Delay::default(),
+            uplink_bandwidth: None,
+            downlink_bandwidth: None,
+            loss_rate: 0.0,
+            retransmit_timeout_ms: default_retransmit_timeout_ms(),
+        }
+    }
+}
+
+/// Which way a message is going
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Client to server
+    Uplink,
+    /// Server to client
+    Downlink,
+}
+
+// Past this many losses in a row, a real connection would've been dropped. Capping it keeps a
+// loss rate of 1 from looping forever.
+const MAX_RETRANSMITS: u32 = 16;
+
+/// A network model that's ready to connect clients. This is where a trace gets loaded, once, and
+/// shared by every client.
+#[derive(Clone, Debug)]
+pub struct Network {
+    model: Arc<NetworkModel>,
+    trace: Option<Arc<Vec<f64>>>,
+}
+
+impl Network {
+    /// Checks the model, and loads its trace if it has one
+    pub fn new(model: NetworkModel) -> Result<Network, Box<dyn StdError>> {
+        if !(0.0..=1.0).contains(&model.uplink_share) || !(0.0..=1.0).contains(&model.loss_rate) {
+            return Err("uplink_share and loss_rate have to be between 0 and 1".into());
+        }
+        // A bandwidth of 0 would take forever to send anything
+        for bandwidth in [model.uplink_bandwidth, model.downlink_bandwidth]
+            .iter()
+            .flatten()
+        {
+            if !(bandwidth.is_finite() && *bandwidth > 0.0) {
+                return Err(format!("bandwidths have to be positive, got {}", bandwidth).into());
+            }
+        }
+        if !(model.retransmit_timeout_ms.is_finite() && model.retransmit_timeout_ms >= 0.0) {
+            return Err(format!(
+                "retransmit_timeout_ms can't be negative, got {}",
+                model.retransmit_timeout_ms
+            )
+            .into());
+        }
+        if let RttSource::PerClient(distr) = &model.rtt {
+            distr.check().map_err(|e| format!("rtt: {}", e))?;
+        }
+        model
+            .uplink_jitter
+            .check()
+            .map_err(|e| format!("uplink_jitter: {}", e))?;
+        model
+            .downlink_jitter
+            .check()
+            .map_err(|e| format!("downlink_jitter: {}", e))?;
+
+        let trace = match &model.rtt {
+            RttSource::Trace { path } => Some(Arc::new(load_trace(&fs::read_to_string(path)?)?)),
+            RttSource::PerClient(_) => None,
+        };
+
+        Ok(Network {
+            model: Arc::new(model),
+            trace,
+        })
+    }
+
+    /// A network that doesn't delay anything
+    pub fn instant() -> Network {
+        Network::new(NetworkModel::default()).unwrap()
+    }
+
+    /// Puts a new client's transport behind this network. Everything random about the client's
+    /// connection, including the delay of every message it sends, comes from the given RNG, so a
+    /// seeded RNG gives the same delays every run.
+    pub fn connect<T, R: Rng>(&self, rng: &mut R, inner: T) -> DelayedTransport<T> {
+        let (rtt_ms, cursor) = match (&self.model.rtt, &self.trace) {
+            (RttSource::Trace { .. }, Some(trace)) => (0.0, rng.gen_range(0, trace.len())),
+            (RttSource::PerClient(distr), _) => (distr.sample(rng), 0),
+            (RttSource::Trace { .. }, None) => unreachable!("traces are loaded in Network::new"),
+        };
+
+        DelayedTransport {
+            inner,
+            network: self.clone(),
+            rtt_ms,
+            cursor: AtomicUsize::new(cursor),
+            rng: Mutex::new(StdRng::seed_from_u64(rng.gen())),
+        }
+    }
+
+    /// Samples the one-way delay of a message of the given size, given the RTT of the round trip
+    /// it's part of
+    pub fn one_way_delay<R: Rng>(
+        &self,
+        rng: &mut R,
+        direction: Direction,
+        rtt_ms: f64,
+        num_bytes: usize,
+    ) -> Duration {
+        let model = &self.model;
+        let (share, jitter, bandwidth) = match direction {
+            Direction::Uplink => (
+                model.uplink_share,
+                &model.uplink_jitter,
+                model.uplink_bandwidth,
+            ),
+            Direction::Downlink => (
+                1.0 - model.uplink_share,
+                &model.downlink_jitter,
+                model.downlink_bandwidth,
+            ),
+        };
+
+        let mut ms = rtt_ms * share + jitter.sample(rng);
+        if let Some(bytes_per_sec) = bandwidth {
+            ms += 1000.0 * num_bytes as f64 / bytes_per_sec;
+        }
+
+        // Every lost transmission costs a timeout, and the timeout doubles each time
+        let mut timeout = model.retransmit_timeout_ms;
+        for _ in 0..MAX_RETRANSMITS {
+            if !rng.gen_bool(model.loss_rate) {
+                break;
+            }
+            ms += timeout;
+            timeout *= 2.0;
+        }
+
+        Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+    }
+}
+
+/// Parses an RTT trace. The first column of every line is an RTT in milliseconds. The first line
+/// can be a header.
+fn load_trace(csv: &str) -> Result<Vec<f64>, Box<dyn StdError>> {
+    let mut rtts = Vec::new();
+    for (i, line) in csv.lines().enumerate() {
+        let field = line.split(',').next().unwrap_or("").trim();
+        if field.is_empty() {
+            continue;
+        }
+        match field.parse::<f64>() {
+            Ok(rtt) if rtt >= 0.0 => rtts.push(rtt),
+            Err(_) if i == 0 => continue,
+            _ => {
+                return Err(format!("line {} of the trace isn't an RTT: {:?}", i + 1, line).into())
+            }
+        }
+    }
+
+    if rtts.is_empty() {
+        Err("the trace is empty".into())
+    } else {
+        Ok(rtts)
+    }
+}
+
+/// A transport that delays every message according to a network model. Messages wait on the
+/// client's thread, never the server's. Get one from `Network::connect`.
+pub struct DelayedTransport<T> {
+    inner: T,
+    network: Network,
+    rtt_ms: f64,
+    /// Where this client is in the trace, if there is one
+    cursor: AtomicUsize,
+    /// Where this client's per-message delays come from
+    rng: Mutex<StdRng>,
+}
+
+impl<T> DelayedTransport<T> {
+    /// Returns the RTT of the next round trip
+    pub(crate) fn next_rtt(&self) -> f64 {
+        match &self.network.trace {
+            Some(trace) => trace[self.cursor.fetch_add(1, SeqCst) % trace.len()],
+            None => self.rtt_ms,
+        }
+    }
+
+    /// Samples the delay of the next message in the given direction
+    pub(crate) fn next_delay(
+        &self,
+        direction: Direction,
+        rtt_ms: f64,
+        num_bytes: usize,
+    ) -> Duration {
+        let mut rng = self.rng.lock().unwrap();
+        self.network
+            .one_way_delay(&mut *rng, direction, rtt_ms, num_bytes)
+    }
+}
+
+impl<T: Transport> Transport for DelayedTransport<T> {
+    fn round_trip(&self, req: &Envelope) -> Result<Envelope, Error> {
+        let rtt_ms = self.next_rtt();
+
+        let up = self.next_delay(Direction::Uplink, rtt_ms, req.body.len());
+        sleep(up);
+
+        // Errors come back over the network too, they're just small
+        let res = self.inner.round_trip(req);
+        let resp_len = res.as_ref().map(|resp| resp.body.len()).unwrap_or(0);
+        let down = self.next_delay(Direction::Downlink, rtt_ms, resp_len);
+        sleep(down);
+
+        res
+    }
+}
+
+// Each part of the delay model adds what it should
+#[test]
+fn test_one_way_delay() {
+    let mut rng = rand::thread_rng();
+    let ms = |d: Duration| (d.as_secs_f64() * 1000.0).round() as u64;
+
+    let network = Network::new(NetworkModel {
+        uplink_share: 0.25,
+        downlink_jitter: Delay::Constant { ms: 5.0 },
+        uplink_bandwidth: Some(1000.0),
+        ..NetworkModel::default()
+    })
+    .unwrap();
+    // A quarter of the RTT, plus 500 bytes at 1000 bytes/s
+    let up = network.one_way_delay(&mut rng, Direction::Uplink, 100.0, 500);
+    assert_eq!(ms(up), 25 + 500);
+    // The rest of the RTT, plus jitter. There's no bandwidth limit this way.
+    let down = network.one_way_delay(&mut rng, Direction::Downlink, 100.0, 500);
+    assert_eq!(ms(down), 75 + 5);
+
+    // A message that's always lost gets retransmitted until we give up, with the timeout doubling
+    // each time
+    let network = Network::new(NetworkModel {
+        loss_rate: 1.0,
+        retransmit_timeout_ms: 1.0,
+        ..NetworkModel::default()
+    })
+    .unwrap();
+    let up = network.one_way_delay(&mut rng, Direction::Uplink, 0.0, 0);
+    assert_eq!(ms(up), (1 << MAX_RETRANSMITS) - 1);
+
+    // Models that would panic or stall a client are turned away up front
+    let bad_models = [
+        NetworkModel {
+            loss_rate: 1.5,
+            ..NetworkModel::default()
+        },
+        NetworkModel {
+            downlink_bandwidth: Some(0.0),
+            ..NetworkModel::default()
+        },
+        NetworkModel {
+            uplink_bandwidth: Some(f64::NAN),
+            ..NetworkModel::default()
+        },
+        NetworkModel {
+            rtt: RttSource::PerClient(Delay::Normal {
+                mean_ms: 50.0,
+                std_ms: -1.0,
+            }),
+            ..NetworkModel::default()
+        },
+        NetworkModel {
+            uplink_jitter: Delay::Normal {
+                mean_ms: 5.0,
+                std_ms: f64::NAN,
+            },
+            ..NetworkModel::default()
+        },
+        NetworkModel {
+            retransmit_timeout_ms: -1.0,
+            ..NetworkModel::default()
+        },
+    ];
+    for bad_model in bad_models {
+        assert!(Network::new(bad_model).is_err());
+    }
+}
+
+// Clients each keep their own RTT, or replay the trace in order
+#[test]
+fn test_rtt_sources() {
+    let mut rng = rand::thread_rng();
+
+    let network = Network::new(NetworkModel {
+        rtt: RttSource::PerClient(Delay::Normal {
+            mean_ms: 50.0,
+            std_ms: 10.0,
+        }),
+        ..NetworkModel::default()
+    })
+    .unwrap();
+    let link = network.connect(&mut rng, ());
+    let rtt = link.next_rtt();
+    assert_eq!(link.next_rtt(), rtt);
+
+    let trace = load_trace("rtt_ms,timestamp\n10,0\n20,1\n\n30,2\n").unwrap();
+    assert_eq!(trace, vec![10.0, 20.0, 30.0]);
+    let model = NetworkModel {
+        rtt: RttSource::Trace {
+            path: "rtts.csv".into(),
+        },
+        ..NetworkModel::default()
+    };
+    let network = Network {
+        model: Arc::new(model),
+        trace: Some(Arc::new(trace)),
+    };
+    let link = network.connect(&mut rng, ());
+    let first = link.next_rtt();
+    let rest: Vec<f64> = (0..3).map(|_| link.next_rtt()).collect();
+    let expected: Vec<f64> = match first as u64 {
+        10 => vec![20.0, 30.0, 10.0],
+        20 => vec![30.0, 10.0, 20.0],
+        _ => vec![10.0, 20.0, 30.0],
+    };
+    assert_eq!(rest, expected);
+
+    assert!(load_trace("rtt_ms\n").is_err());
+    assert!(load_trace("10\nfast\n").is_err());
+}
+
+// Clients connected with identically seeded RNGs see identical delays
+#[test]
+fn test_seeded_connect() {
+    let network = Network::new(NetworkModel {
+        rtt: RttSource::PerClient(Delay::Normal {
+            mean_ms: 50.0,
+            std_ms: 10.0,
+        }),
+        uplink_jitter: Delay::Normal {
+            mean_ms: 5.0,
+            std_ms: 2.0,
+        },
+        loss_rate: 0.1,
+        ..NetworkModel::default()
+    })
+    .unwrap();
+
+    let delays = |seed| {
+        let link = network.connect(&mut StdRng::seed_from_u64(seed), ());
+        let rtt_ms = link.next_rtt();
+        let delays: Vec<Duration> = (0..8)
+            .map(|_| link.next_delay(Direction::Uplink, rtt_ms, 100))
+            .collect();
+        (rtt_ms, delays)
+    };
+    assert_eq!(delays(1), delays(1));
+    assert_ne!(delays(1), delays(2));
+}
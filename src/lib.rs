@@ -1,10 +1,25 @@
 #![allow(non_snake_case, mixed_script_confusables, confusable_idents)]
-#![feature(non_ascii_idents)]
 
 #[macro_use]
 extern crate lazy_static;
 
 pub mod abe;
+pub mod async_server;
+pub mod blind_bls;
+pub mod blind_rsa;
+pub mod clause_schnorr;
 pub mod common;
+pub mod compare;
+pub mod error;
+pub mod export;
+pub mod latency;
+pub mod loadgen;
+pub mod results;
+pub mod retry;
+pub mod scenario;
 pub mod schnorr;
+pub mod seed;
+pub mod session;
+pub mod sim;
+pub mod transport;
 pub mod webserver;
@@ -0,0 +1,534 @@
+/*
+An open-loop load generator. New sessions arrive as a Poisson process at a fixed rate for a fixed
+amount of time, regardless of how quickly the server gets through them. This is unlike the closed
+loop in bench_all.rs, where the only number that comes out is how long a fixed batch of clients
+took. Here every session's end-to-end latency goes into a histogram, so we can see the tail.
+
+Latency is measured from when a session was scheduled to arrive, not from when its thread got
+around to starting it. Otherwise a slow load generator would hide exactly the delays we're trying
+to measure.
+
+Each session gets a thread of its own, so a server that falls far enough behind would have the
+load generator spawning threads without end. Past a limit on how many sessions are in flight, new
+arrivals are dropped instead, and the report says how many.
+*/
+
+use crate::{
+    abe::Abe,
+    blind_bls::BlindBls,
+    blind_rsa::BlindRsaRandomized,
+    clause_schnorr::ClauseBlindSchnorr,
+    common::BlindSigProtocol,
+    error::Error,
+    latency::Network,
+    retry::{ClientStats, RetryPolicy},
+    scenario::{ArrivalProcess, LatencyModel, Scenario, Scheme, ServerBackend},
+    schnorr::BlindSchnorr,
+    seed::{stream_rng, SeededDistr, Stream},
+    session::{AdmissionPolicy, SessionStore},
+    transport::{start_channel_server, HttpTransport, Transport},
+    webserver::{make_client, start_backend},
+};
+use std::{
+    error::Error as StdError,
+    sync::{atomic::Ordering::SeqCst, mpsc, Arc},
+    thread::{sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use hdrhistogram::Histogram;
+use rand_distr::Normal;
+use serde::{Deserialize, Serialize};
+
+/// How the load generator reaches the server it starts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransportKind {
+    /// Over HTTP, with the server listening on the given address
+    Http(String),
+    /// Over channels, to a server in the same process
+    InMemory,
+}
+
+/// Everything that describes a load test
+#[derive(Clone, Debug)]
+pub struct LoadConfig {
+    pub scheme: Scheme,
+    /// How long new sessions keep arriving for. Sessions still in flight at the end are waited
+    /// for.
+    pub duration: Duration,
+    /// Mean number of new sessions per second
+    pub arrival_rate: f64,
+    /// Number of threads the server gets
+    pub pool_size: usize,
+    pub latency: LatencyModel,
+    pub admission: AdmissionPolicy,
+    pub session_timeout: Duration,
+    pub retry_policy: RetryPolicy,
+    pub transport: TransportKind,
+    /// Seeds the arrival schedule and the latency model. `None` means a different workload every
+    /// run.
+    pub seed: Option<u64>,
+    /// How the HTTP server handles requests. The in-memory server is always a thread pool.
+    pub server_backend: ServerBackend,
+    /// The most sessions in flight at once. Arrivals past this are dropped.
+    pub max_in_flight: usize,
+}
+
+impl Default for LoadConfig {
+    /// Abe on 4 threads, 10 new sessions per second for 30 seconds, over HTTP. The latency is the
+    /// same as in the default scenario.
+    fn default() -> LoadConfig {
+        LoadConfig {
+            scheme: Scheme::Abe,
+            duration: Duration::from_secs(30),
+            arrival_rate: 10.0,
+            pool_size: 4,
+            latency: Scenario::default().latency,
+            admission: AdmissionPolicy::Reject,
+            session_timeout: Duration::from_secs(30),
+            retry_policy: RetryPolicy::default(),
+            transport: TransportKind::Http("localhost:14148".to_string()),
+            seed: None,
+            server_backend: ServerBackend::ThreadPool,
+            max_in_flight: 1000,
+        }
+    }
+}
+
+impl LoadConfig {
+    /// Checks that the load test can run, and finish. Sessions have to arrive at a finite rate,
+    /// slow enough that consecutive arrivals are at least a nanosecond apart, and the server and
+    /// the load generator both need room for at least one session.
+    pub fn check(&self) -> Result<(), String> {
+        let rate = self.arrival_rate;
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(format!("arrival rate {} isn't positive and finite", rate));
+        }
+        if Duration::from_secs_f64(rate.recip()).is_zero() {
+            return Err(format!("arrival rate {} is too high", rate));
+        }
+        if self.pool_size == 0 {
+            return Err("the server needs at least one thread".to_string());
+        }
+        if self.max_in_flight == 0 {
+            return Err("at least one session has to be allowed in flight".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Percentiles of end-to-end session latency, in milliseconds. Only sessions that got a signature
+/// count.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+    pub max: f64,
+}
+
+/// A point on the CDF of session latency: `quantile` of sessions took at most `latency_ms`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CdfPoint {
+    pub latency_ms: f64,
+    pub quantile: f64,
+}
+
+/// What came out of a load test
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoadReport {
+    pub scheme: Scheme,
+    pub pool_size: usize,
+    /// The configured arrival rate, in sessions per second
+    pub arrival_rate: f64,
+    pub latency_model: LatencyModel,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub server_backend: ServerBackend,
+    /// How long sessions were arriving for, in seconds
+    pub duration_s: f64,
+    /// How long until the last session was over, in seconds
+    pub elapsed_s: f64,
+    pub sessions_started: u64,
+    /// Arrivals that never started, because too many sessions were already in flight
+    #[serde(default)]
+    pub sessions_dropped: u64,
+    pub sessions_completed: u64,
+    /// Sessions whose client gave up because the server kept turning it away
+    pub sessions_rejected: u64,
+    /// Sessions that went wrong in some other way
+    pub sessions_failed: u64,
+    /// Number of times the server turned a new session away, counting ones that got in on a
+    /// later try
+    pub server_rejections: u64,
+    pub client_retries: u64,
+    /// Completed sessions per second, over the whole run
+    pub throughput: f64,
+    pub latency_ms: LatencySummary,
+    /// The latency at every percentile, and at a few more points in the tail
+    #[serde(default)]
+    pub latency_cdf: Vec<CdfPoint>,
+}
+
+/// Starts a server for the configured scheme, puts it under load, and shuts it down again. Fails if
+/// the config doesn't pass `LoadConfig::check`, if the latency model is invalid or its RTT trace
+/// can't be loaded, or if it asks for the async backend in memory.
+pub fn run(config: &LoadConfig) -> Result<LoadReport, Box<dyn StdError>> {
+    config.check()?;
+    if config.transport == TransportKind::InMemory
+        && config.server_backend != ServerBackend::ThreadPool
+    {
+        return Err("the in-memory server only comes as a thread pool".into());
+    }
+    let network = config.latency.network()?;
+    let server_latency = config.latency.server_distr()?;
+    let report = match config.scheme {
+        Scheme::BlindSchnorr => run_scheme::<BlindSchnorr>(config, &network, server_latency),
+        Scheme::ClauseBlindSchnorr => {
+            run_scheme::<ClauseBlindSchnorr>(config, &network, server_latency)
+        }
+        Scheme::Abe => run_scheme::<Abe>(config, &network, server_latency),
+        Scheme::BlindRsa => run_scheme::<BlindRsaRandomized>(config, &network, server_latency),
+        Scheme::BlindBls => run_scheme::<BlindBls>(config, &network, server_latency),
+    };
+
+    Ok(report)
+}
+
+fn run_scheme<S: BlindSigProtocol + 'static>(
+    config: &LoadConfig,
+    network: &Network,
+    server_latency: Normal<f64>,
+) -> LoadReport {
+    let latency_distr = SeededDistr::new(
+        server_latency,
+        stream_rng(config.seed, LABEL, Stream::ServerLatency),
+    );
+    let store = Arc::new(SessionStore::new(config.session_timeout, config.admission));
+
+    match &config.transport {
+        TransportKind::InMemory => {
+            // The server stops once the transport is dropped
+            let (_privkey, pubkey, transport) =
+                start_channel_server::<S, _>(config.pool_size, store.clone(), latency_distr);
+            drive::<S, _>(config, &pubkey, network, transport, &store)
+        }
+        TransportKind::Http(addr) => {
+            let (_privkey, pubkey, stop_var) = start_backend::<S, _>(
+                config.server_backend,
+                addr,
+                config.pool_size,
+                store.clone(),
+                latency_distr,
+            );
+            // Give the server a moment to start listening, so the first sessions aren't charged
+            // for it
+            sleep(Duration::from_millis(500));
+            let transport = HttpTransport::new(addr);
+            let report = drive::<S, _>(config, &pubkey, network, transport, &store);
+
+            stop_var.store(true, SeqCst);
+            report
+        }
+    }
+}
+
+// Names the load generator's streams of randomness
+const LABEL: &str = "loadgen";
+
+/// Returns when each session arrives, measured from the start of the run. Arrivals are a Poisson
+/// process, and stop once the configured duration is up.
+fn arrival_schedule(config: &LoadConfig) -> Vec<Duration> {
+    let mut arrival_rng = stream_rng(config.seed, LABEL, Stream::Arrivals);
+    let mean_interarrival = Duration::from_secs_f64(config.arrival_rate.recip());
+
+    let mut schedule = Vec::new();
+    let mut next_arrival = Duration::default();
+    while next_arrival < config.duration {
+        schedule.push(next_arrival);
+        next_arrival += ArrivalProcess::Poisson.sample(&mut arrival_rng, mean_interarrival);
+    }
+    schedule
+}
+
+/// Runs clients against an already running server, and tallies up how they did
+fn drive<S, T>(
+    config: &LoadConfig,
+    pubkey: &S::Pubkey,
+    network: &Network,
+    transport: T,
+    store: &SessionStore<S::ServerSession>,
+) -> LoadReport
+where
+    S: BlindSigProtocol,
+    T: Transport + Clone,
+{
+    let mut network_rng = stream_rng(config.seed, LABEL, Stream::Network);
+    let (result_send, results) = mpsc::channel::<(Result<ClientStats, Error>, Duration)>();
+
+    let schedule = arrival_schedule(config);
+    let start = Instant::now();
+    let end = start + config.duration;
+    let mut threads: Vec<JoinHandle<()>> = Vec::new();
+    let mut sessions_dropped = 0;
+
+    for offset in schedule {
+        // Arrivals are scheduled against the clock rather than relative to each other, so time
+        // spent spawning threads doesn't slow down the arrival rate
+        let next_arrival = start + offset;
+        let now = Instant::now();
+        if next_arrival > now {
+            sleep(next_arrival - now);
+        }
+
+        // Connect even if the session is dropped, so the sessions after it get the same network
+        // conditions either way
+        let transport = network.connect(&mut network_rng, transport.clone());
+
+        // Only the threads that are still going count as in flight
+        let (done, running): (Vec<_>, Vec<_>) =
+            threads.into_iter().partition(JoinHandle::is_finished);
+        for thread in done {
+            thread.join().unwrap();
+        }
+        threads = running;
+        if threads.len() >= config.max_in_flight {
+            sessions_dropped += 1;
+            continue;
+        }
+
+        let client = make_client::<S, _>(transport, pubkey.clone(), config.retry_policy.clone());
+        let arrival = next_arrival;
+        let result_send = result_send.clone();
+        threads.push(std::thread::spawn(move || {
+            let res = client();
+            // The receiver outlives every client
+            result_send.send((res, arrival.elapsed())).unwrap();
+        }));
+    }
+
+    // The run lasts at least the configured duration, even if the last arrival came early
+    let now = Instant::now();
+    if end > now {
+        sleep(end - now);
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let elapsed = start.elapsed();
+    drop(result_send);
+
+    // Latencies are recorded in microseconds, to 3 significant figures, up to an hour
+    let mut hist = Histogram::<u64>::new_with_bounds(1, 3_600_000_000, 3).unwrap();
+    let mut report = LoadReport {
+        scheme: config.scheme,
+        pool_size: config.pool_size,
+        arrival_rate: config.arrival_rate,
+        latency_model: config.latency.clone(),
+        seed: config.seed,
+        server_backend: config.server_backend,
+        duration_s: config.duration.as_secs_f64(),
+        elapsed_s: elapsed.as_secs_f64(),
+        sessions_started: 0,
+        sessions_dropped,
+        sessions_completed: 0,
+        sessions_rejected: 0,
+        sessions_failed: 0,
+        server_rejections: store.num_rejected() as u64,
+        client_retries: 0,
+        throughput: 0.0,
+        latency_ms: LatencySummary::default(),
+        latency_cdf: Vec::new(),
+    };
+
+    for (res, latency) in results {
+        report.sessions_started += 1;
+        match res {
+            Ok(stats) => {
+                report.sessions_completed += 1;
+                report.client_retries += stats.num_retries as u64;
+                hist.saturating_record(latency.as_micros() as u64);
+            }
+            Err(e) if RetryPolicy::is_retryable(&e) => report.sessions_rejected += 1,
+            Err(_) => report.sessions_failed += 1,
+        }
+    }
+
+    report.throughput = report.sessions_completed as f64 / elapsed.as_secs_f64();
+    if !hist.is_empty() {
+        let ms = |us: u64| us as f64 / 1000.0;
+        report.latency_ms = LatencySummary {
+            min: ms(hist.min()),
+            mean: hist.mean() / 1000.0,
+            p50: ms(hist.value_at_quantile(0.5)),
+            p90: ms(hist.value_at_quantile(0.9)),
+            p99: ms(hist.value_at_quantile(0.99)),
+            p999: ms(hist.value_at_quantile(0.999)),
+            max: ms(hist.max()),
+        };
+
+        let percentiles = (0..100).map(|p| p as f64 / 100.0);
+        let tail = [0.995, 0.999, 0.9999, 1.0];
+        report.latency_cdf = percentiles
+            .chain(tail.iter().copied())
+            .map(|quantile| CdfPoint {
+                latency_ms: ms(hist.value_at_quantile(quantile)),
+                quantile,
+            })
+            .collect();
+    }
+
+    report
+}
+
+// Every session that starts is accounted for, and the percentiles are in order
+#[test]
+fn test_load_report() {
+    use crate::latency::{Delay, NetworkModel, RttSource};
+
+    let config = LoadConfig {
+        scheme: Scheme::BlindSchnorr,
+        duration: Duration::from_secs(1),
+        arrival_rate: 20.0,
+        pool_size: 1,
+        // Delay messages on the client side, with a 10ms RTT
+        latency: LatencyModel::Network(NetworkModel {
+            rtt: RttSource::PerClient(Delay::Constant { ms: 10.0 }),
+            ..NetworkModel::default()
+        }),
+        transport: TransportKind::InMemory,
+        ..LoadConfig::default()
+    };
+    let report = run(&config).unwrap();
+
+    assert!(report.sessions_started > 0);
+    assert_eq!(
+        report.sessions_started,
+        report.sessions_completed + report.sessions_rejected + report.sessions_failed
+    );
+    assert_eq!(report.sessions_failed, 0);
+
+    let l = &report.latency_ms;
+    assert!(l.min <= l.p50 && l.p50 <= l.p90 && l.p90 <= l.p99);
+    assert!(l.p99 <= l.p999 && l.p999 <= l.max);
+    let cdf = &report.latency_cdf;
+    assert_eq!(cdf.last().unwrap().latency_ms, l.max);
+    assert!(cdf.windows(2).all(|w| w[0].latency_ms <= w[1].latency_ms));
+    // Every session takes at least two round trips
+    assert!(l.min >= 20.0);
+}
+
+// With a seed, the same sessions arrive every run
+#[test]
+fn test_seeded_load() {
+    let config = LoadConfig {
+        scheme: Scheme::BlindSchnorr,
+        duration: Duration::from_secs(1),
+        arrival_rate: 20.0,
+        pool_size: 1,
+        latency: LatencyModel::Normal {
+            mean_ms: 1.0,
+            std_ms: 1.0,
+        },
+        transport: TransportKind::InMemory,
+        seed: Some(7),
+        ..LoadConfig::default()
+    };
+    let schedule = arrival_schedule(&config);
+    assert_eq!(arrival_schedule(&config), schedule);
+    let reseeded = LoadConfig {
+        seed: Some(8),
+        ..config.clone()
+    };
+    assert_ne!(arrival_schedule(&reseeded), schedule);
+    let unseeded = LoadConfig {
+        seed: None,
+        ..config.clone()
+    };
+    assert_ne!(arrival_schedule(&unseeded), arrival_schedule(&unseeded));
+
+    // The run follows the schedule
+    let report = run(&config).unwrap();
+    assert_eq!(report.seed, Some(7));
+    assert_eq!(report.sessions_started, schedule.len() as u64);
+    assert_eq!(report.sessions_dropped, 0);
+
+    // There's no async server without HTTP
+    let config = LoadConfig {
+        server_backend: ServerBackend::Async,
+        ..config
+    };
+    assert!(run(&config).is_err());
+}
+
+// Arrivals past the limit on sessions in flight are dropped, and counted, rather than started
+#[test]
+fn test_max_in_flight() {
+    let config = LoadConfig {
+        scheme: Scheme::BlindSchnorr,
+        duration: Duration::from_secs(1),
+        arrival_rate: 50.0,
+        pool_size: 1,
+        // Every session takes at least 200ms, so one at a time can't keep up
+        latency: LatencyModel::Normal {
+            mean_ms: 100.0,
+            std_ms: 0.0,
+        },
+        transport: TransportKind::InMemory,
+        seed: Some(7),
+        max_in_flight: 1,
+        ..LoadConfig::default()
+    };
+    let report = run(&config).unwrap();
+
+    assert!(report.sessions_dropped > 0);
+    assert_eq!(
+        report.sessions_started + report.sessions_dropped,
+        arrival_schedule(&config).len() as u64
+    );
+    // The one session in flight never has to wait for the server
+    assert_eq!(report.sessions_completed, report.sessions_started);
+    assert_eq!(report.client_retries, 0);
+}
+
+// Configs that would never finish, or never start, are turned away before anything runs
+#[test]
+fn test_check_config() {
+    assert!(LoadConfig::default().check().is_ok());
+
+    let bad_configs = [
+        LoadConfig {
+            arrival_rate: 0.0,
+            ..LoadConfig::default()
+        },
+        LoadConfig {
+            arrival_rate: f64::NAN,
+            ..LoadConfig::default()
+        },
+        LoadConfig {
+            arrival_rate: f64::INFINITY,
+            ..LoadConfig::default()
+        },
+        // Arrivals less than a nanosecond apart all land at once
+        LoadConfig {
+            arrival_rate: 1e10,
+            ..LoadConfig::default()
+        },
+        LoadConfig {
+            pool_size: 0,
+            ..LoadConfig::default()
+        },
+        LoadConfig {
+            max_in_flight: 0,
+            ..LoadConfig::default()
+        },
+    ];
+    for config in &bad_configs {
+        assert!(config.check().is_err());
+        assert!(run(config).is_err());
+    }
+}
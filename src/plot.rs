@@ -1,130 +1,553 @@
-use std::{error::Error, fs::File, io::BufReader};
+use blind_sig_bench::{
+    loadgen::LoadReport,
+    results::{discover, discover_steps, BenchResult},
+    scenario::{Scenario, Scheme, ASYNC_GROUP_SUFFIX, SCENARIO_VAR},
+};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 use gnuplot::{
+    AlignType::{AlignBottom, AlignLeft, AlignRight, AlignTop},
     AutoOption::{Auto, Fix},
-    AxesCommon, Coordinate, DashType, Figure,
-    PlotOption::{self, Caption, Color, LineStyle, PointSymbol},
+    Axes2D, AxesCommon, Coordinate, DashType, Figure,
+    LegendOption::Placement,
+    PlotOption::{BorderColor, Caption, Color, FillAlpha, LineStyle, PointSize, PointSymbol},
     Tick, TickOption,
 };
-use serde::{Deserialize, Serialize};
 
-const INTERARRIVAL_TIMES: &[usize] = &[1, 10, 50, 90, 130];
-const THREADPOOL_SIZES: &[usize] = &[1, 4, 16];
+const USAGE: &str = "\
+Usage: plot [OPTIONS]
+
+Plots benchmark results. These figures are saved in --out-dir, as SVG, PNG, or both:
+
+  server_runtime    Server runtime against workload, with confidence intervals on the mean, and
+                    a band of one standard deviation
+  throughput        Sessions per second against server threads, for every workload
+  step_costs        Cost of each step of the protocol, for a single session
+  latency_cdf       Session latency CDFs from load generator reports
+
+Figures with no data are skipped.
+
+Options:
+  --criterion-dir DIR  Webserver benchmark results [./ec2_data/webserver_bench]. Your own runs are
+                       in target/criterion.
+  --piecewise-dir DIR  Per-step benchmark results [./ec2_data/piecewise_bench]
+  --loadgen-dir DIR    A directory of JSON reports from the load generator [none]
+  --out-dir DIR        Where to save the figures [plots]
+  --out PATH           Where to save the server runtime figure [DIR/server_runtime]
+  --format FORMAT      svg, png, or both [the extension of --out, if it has one, or both]
+  --size WxH           Size of each figure in pixels [560x350]
+  --scenario PATH      Only plot the webserver benchmarks in this scenario [$BENCH_SCENARIO, if
+                       it's set]
+  --help               Print this message";
 
-static SCHNORR_STR: &str = "Sequential Blind Schnorr";
-static ABE_STR: &str = "Parallel Abe";
+// Colors for groups that aren't one of our schemes
+const PALETTE: &[&str] = &["black", "brown", "dark-cyan", "dark-magenta", "gold"];
 
-// We do this weird structure bc that's how criterion formats its JSON outputs
-#[derive(Deserialize, Serialize)]
-struct Mean {
-    point_estimate: f64,
+/// How a group's lines look. Each of its lines gets the next point symbol. A scheme on the async
+/// server keeps its color, but its lines are dot-dot-dashed.
+fn line_style(group: &str, group_idx: usize) -> (&'static str, DashType, &'static [char]) {
+    if let Some(scheme_group) = group.strip_suffix(ASYNC_GROUP_SUFFIX) {
+        let (color, _, symbols) = line_style(scheme_group, group_idx);
+        return (color, DashType::DotDotDash, symbols);
+    }
+
+    let scheme = Scheme::ALL.iter().find(|s| s.group_name() == group);
+    match scheme {
+        Some(Scheme::Abe) => ("red", DashType::Solid, &['O', 'R', 'T', 'D']),
+        Some(Scheme::BlindRsa) => ("dark-green", DashType::Dash, &['o', 'r', 't', 'd']),
+        Some(Scheme::BlindSchnorr) => ("blue", DashType::Solid, &['S', 's']),
+        Some(Scheme::ClauseBlindSchnorr) => ("orange", DashType::DotDash, &['O', 'R', 'T', 'D']),
+        Some(Scheme::BlindBls) => ("purple", DashType::Dot, &['o', 'r', 't', 'd']),
+        None => (
+            PALETTE[group_idx % PALETTE.len()],
+            DashType::Solid,
+            &['O', 'R', 'T', 'D'],
+        ),
+    }
 }
-#[derive(Deserialize, Serialize)]
-struct Estimate {
-    mean: Mean,
+
+/// Returns whether the scenario would've run the given benchmark
+fn in_scenario(scenario: &Scenario, result: &BenchResult) -> bool {
+    let id = &result.id;
+    id.num_clients == scenario.num_clients
+        && scenario.interarrival_times_ms.contains(&id.eiat_ms)
+        && scenario.schemes.iter().any(|run| {
+            scenario.group_name(run.scheme) == id.group && run.pool_sizes.contains(&id.pool_size)
+        })
 }
 
-/// Returns the mean server runtime (in ns) for the benchmark on the given scheme with
-/// threadpool_size many threads and expected interarrival time of eiat
-fn get_mean_server_runtime(
-    scheme: &str,
-    threadpool_size: usize,
-    eiat: usize,
-) -> Result<f64, Box<dyn Error>> {
-    // Steps 1 and 3 of the protocol are done on the server side
-    let filename = format!(
-        "./ec2_data/webserver_bench/{}/{}-core server handling 100 clients at \
-         {}ms EIAT/new/estimates.json",
-        scheme, threadpool_size, eiat
-    );
-    println!("filename == {}", filename);
-    let file = File::open(filename)?;
-    let estimate: Estimate = serde_json::from_reader(BufReader::new(file))?;
-
-    Ok(estimate.mean.point_estimate)
+/// Returns the sorted, deduplicated values
+fn distinct(mut xs: Vec<f64>) -> Vec<f64> {
+    xs.sort_by(f64::total_cmp);
+    xs.dedup();
+    xs
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Sets up a log-scale x-axis with a tick at every value, and a little room on either side
+fn set_log_x_axis(axes: &mut Axes2D, xs: &[f64], label: &str, format: &str) {
+    let ticks: Vec<Tick<_, &str>> = xs.iter().map(|&n| Tick::Major(n, Auto)).collect();
+    // The axis is log scale, so the room is a ratio
+    let (x_min, x_max) = (xs[0] / 1.15, xs[xs.len() - 1] * 1.15);
+
+    axes.set_x_log(Some(2f64))
+        .set_x_label(label, &[])
+        .set_x_ticks_custom::<_, &str, _, _>(&ticks, &[TickOption::Format(format)], &[])
+        .set_x_range(Fix(x_min), Fix(x_max));
+}
+
+/// Which files each figure is saved as
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Svg,
+    Png,
+    Both,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "svg" => Ok(Format::Svg),
+            "png" => Ok(Format::Png),
+            "both" => Ok(Format::Both),
+            _ => Err("expected svg, png, or both".to_string()),
+        }
+    }
+}
+
+/// Where to save the figures, and as what
+struct Output {
+    dir: PathBuf,
+    /// Where the server runtime figure goes, if not in `dir`
+    runtime_path: Option<PathBuf>,
+    format: Format,
+    size: (u32, u32),
+}
+
+impl Output {
+    /// Saves the figure under the given name, in the chosen format. The extension is the
+    /// format's, whatever the path's was.
+    fn save(&self, fg: &mut Figure, name: &str) -> Result<(), Box<dyn Error>> {
+        let path = match &self.runtime_path {
+            Some(path) if name == "server_runtime" => path.clone(),
+            _ => self.dir.join(name),
+        };
+        let (width, height) = self.size;
+        if self.format != Format::Png {
+            let svg_path = path.with_extension("svg");
+            fg.save_to_svg(&svg_path, width, height)?;
+            println!("saved {}", svg_path.display());
+        }
+        if self.format != Format::Svg {
+            let png_path = path.with_extension("png");
+            fg.save_to_png(&png_path, width, height)?;
+            println!("saved {}", png_path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Draws an error bar from `lows` to `highs` at each x. gnuplot's error bars are the same length
+/// either side of the point, so a bar is drawn around the middle of its interval, with the point
+/// itself hidden. Whatever the bars are for has to be drawn separately.
+fn interval_bars(axes: &mut Axes2D, xs: &[f64], lows: &[f64], highs: &[f64], color: &str) {
+    let mids = lows.iter().zip(highs).map(|(lo, hi)| (lo + hi) / 2.0);
+    let half_widths = lows.iter().zip(highs).map(|(lo, hi)| (hi - lo) / 2.0);
+    axes.y_error_bars(xs, mids, half_widths, &[Color(color), PointSize(0.0)]);
+}
+
+/// Plots server runtime against workload factor, with a line for every group and pool size
+fn plot_runtime(results: &[BenchResult], out: &Output) -> Result<(), Box<dyn Error>> {
+    // Only mention the number of clients if it isn't the same everywhere
+    let num_clients = results[0].id.num_clients;
+    let show_clients = results.iter().any(|r| r.id.num_clients != num_clients);
+
+    // Workload factor is 1 / expected IAT
+    let workload_factors = distinct(results.iter().map(|r| r.id.workload_factor()).collect());
+
     let mut fg = Figure::new();
     fg.set_enhanced_text(true);
+    let plot = fg.axes2d();
+    set_log_x_axis(plot, &workload_factors, "Workload factor", "%.3f");
+    plot.set_legend(
+        Coordinate::Graph(0.02),
+        Coordinate::Graph(0.98),
+        &[Placement(AlignLeft, AlignTop)],
+        &[],
+    )
+    .set_y_label("Runtime (s)", &[])
+    .set_y_ticks(Some((Auto, 0)), &[], &[])
+    .set_y_range(Fix(0f64), Auto);
 
-    // Workload factor is 1 / expected IAT
-    let workload_factors: Vec<f64> = INTERARRIVAL_TIMES
-        .iter()
-        .map(|&iat| (iat as f64).recip())
-        .collect();
+    // The results are sorted, so each line's points are next to each other, in order
+    let mut groups: Vec<&str> = Vec::new();
+    let mut num_group_lines = 0;
+    for line in results.chunk_by(|a, b| {
+        (&a.id.group, a.id.pool_size, a.id.num_clients)
+            == (&b.id.group, b.id.pool_size, b.id.num_clients)
+    }) {
+        let id = &line[0].id;
+        if groups.last() != Some(&id.group.as_str()) {
+            groups.push(&id.group);
+            num_group_lines = 0;
+        }
+        let (color, dash_type, point_symbols) = line_style(&id.group, groups.len() - 1);
+        let point_symbol = point_symbols[num_group_lines % point_symbols.len()];
+        num_group_lines += 1;
 
-    // x-axis tickmarks are the workload factors
-    let ticks: Vec<Tick<_, &str>> = workload_factors
-        .iter()
-        .map(|&n| Tick::Major(n, Auto))
-        .collect();
+        let mut line_name = format!("{}-thread {}", id.pool_size, id.group);
+        if show_clients {
+            line_name += &format!(", {} clients", id.num_clients);
+        }
 
-    let mut plot = fg
-        .axes2d()
-        .set_legend(Coordinate::Axis(0.90f64), Coordinate::Axis(14f64), &[], &[])
-        .set_x_log(Some(2f64))
-        .set_x_label("Workload factor", &[])
-        .set_y_label("Runtime (s)", &[])
-        .set_y_ticks(Some((Auto, 0)), &[], &[])
-        .set_x_ticks_custom::<_, &str, _, _>(&ticks, &[TickOption::Format("%.3f")], &[])
-        .set_x_range(Fix(0.0069f64), Fix(1.13f64))
-        .set_y_range(Fix(0f64), Fix(15f64));
-
-    // Collect and plot Abe results
-    for (&threadpool_size, &point_type) in THREADPOOL_SIZES.iter().zip(
-        [
-            PlotOption::<&str>::PointSymbol('O'),
-            PointSymbol('R'),
-            PointSymbol('T'),
-        ]
-        .iter(),
-    ) {
-        // Collect (num_sessions, runtime of Abe with num_sessions in sec)
-        let abe_runtimes: Vec<f64> = INTERARRIVAL_TIMES
+        // Collect the runtimes in sec
+        let xs: Vec<f64> = line.iter().map(|r| r.id.workload_factor()).collect();
+        let estimates = line
             .iter()
-            .map(|&eiat| {
-                get_mean_server_runtime(ABE_STR, threadpool_size, eiat).unwrap() / 1_000_000_000f64
-            })
-            .collect();
-
-        // Plot Abe result as a red line with the given point type
-        let line_name = format!("{}-thread {}", threadpool_size, ABE_STR);
-        plot = plot.lines_points(
-            &workload_factors,
-            abe_runtimes,
+            .map(|r| r.estimates())
+            .collect::<Result<Vec<_>, _>>()?;
+        let secs = |ns: f64| ns / 1_000_000_000f64;
+        let means: Vec<f64> = estimates.iter().map(|e| secs(e.mean)).collect();
+
+        // Shade one standard deviation either side of the mean, and put error bars on the mean
+        // from its confidence interval
+        let lows: Vec<f64> = estimates.iter().map(|e| secs(e.mean - e.std_dev)).collect();
+        let highs: Vec<f64> = estimates.iter().map(|e| secs(e.mean + e.std_dev)).collect();
+        let ci_lows: Vec<f64> = estimates.iter().map(|e| secs(e.mean_lower)).collect();
+        let ci_highs: Vec<f64> = estimates.iter().map(|e| secs(e.mean_upper)).collect();
+        plot.fill_between(&xs, lows, highs, &[Color(color), FillAlpha(0.15)])
+            .lines_points(
+                &xs,
+                means,
+                &[
+                    Caption(&line_name),
+                    Color(color),
+                    PointSymbol(point_symbol),
+                    LineStyle(dash_type),
+                ],
+            );
+        interval_bars(plot, &xs, &ci_lows, &ci_highs, color);
+    }
+
+    out.save(&mut fg, "server_runtime")
+}
+
+/// Plots sessions per second against server threads, with a line for every group and workload.
+/// Each benchmark runs a fixed number of clients, so this is that number over the runtime.
+fn plot_throughput(results: &[BenchResult], out: &Output) -> Result<(), Box<dyn Error>> {
+    let pool_sizes = distinct(results.iter().map(|r| r.id.pool_size as f64).collect());
+
+    let mut fg = Figure::new();
+    fg.set_enhanced_text(true);
+    let plot = fg.axes2d();
+    set_log_x_axis(plot, &pool_sizes, "Server threads", "%.0f");
+    plot.set_legend(
+        Coordinate::Graph(0.02),
+        Coordinate::Graph(0.98),
+        &[Placement(AlignLeft, AlignTop)],
+        &[],
+    )
+    .set_y_label("Throughput (sessions/s)", &[])
+    .set_y_range(Fix(0f64), Auto);
+
+    // Regroup the results by group and workload, in order of pool size
+    let mut lines = results.to_vec();
+    lines.sort_by(|a, b| {
+        (a.id.group.cmp(&b.id.group))
+            .then(a.id.num_clients.cmp(&b.id.num_clients))
+            .then(a.id.eiat_ms.total_cmp(&b.id.eiat_ms))
+            .then(a.id.pool_size.cmp(&b.id.pool_size))
+    });
+
+    let mut groups: Vec<&str> = Vec::new();
+    let mut num_group_lines = 0;
+    for line in lines.chunk_by(|a, b| {
+        (&a.id.group, a.id.num_clients, a.id.eiat_ms)
+            == (&b.id.group, b.id.num_clients, b.id.eiat_ms)
+    }) {
+        let id = &line[0].id;
+        if groups.last() != Some(&id.group.as_str()) {
+            groups.push(&id.group);
+            num_group_lines = 0;
+        }
+        let (color, dash_type, point_symbols) = line_style(&id.group, groups.len() - 1);
+        let point_symbol = point_symbols[num_group_lines % point_symbols.len()];
+        num_group_lines += 1;
+        let line_name = format!("{} at {}ms EIAT", id.group, id.eiat_ms);
+
+        let xs: Vec<f64> = line.iter().map(|r| r.id.pool_size as f64).collect();
+        let mut throughputs = Vec::new();
+        let mut ci_lows = Vec::new();
+        let mut ci_highs = Vec::new();
+        for r in line {
+            let num_clients = r.id.num_clients as f64;
+            let (throughput, low, high) = r
+                .estimates()?
+                .map_decreasing(|ns| num_clients / (ns / 1_000_000_000f64));
+            throughputs.push(throughput);
+            ci_lows.push(low);
+            ci_highs.push(high);
+        }
+
+        plot.lines_points(
+            &xs,
+            throughputs,
             &[
                 Caption(&line_name),
-                Color("red"),
-                point_type,
-                LineStyle(DashType::Solid),
+                Color(color),
+                PointSymbol(point_symbol),
+                LineStyle(dash_type),
             ],
         );
+        interval_bars(plot, &xs, &ci_lows, &ci_highs, color);
     }
 
-    // Collect Schnorr data
-    let schnorr_runtimes: Vec<f64> = INTERARRIVAL_TIMES
+    out.save(&mut fg, "throughput")
+}
+
+/// Plots a bar for each step of each group, for the smallest number of sessions measured
+fn plot_step_costs(piecewise_dir: &Path, out: &Output) -> Result<(), Box<dyn Error>> {
+    let mut results = discover_steps(piecewise_dir)?;
+    let num_sessions = match results.iter().map(|r| r.id.num_sessions).min() {
+        Some(n) => n,
+        None => {
+            println!(
+                "no per-step benchmarks in {}, skipping",
+                piecewise_dir.display()
+            );
+            return Ok(());
+        }
+    };
+    results.retain(|r| r.id.num_sessions == num_sessions);
+
+    let mut groups: Vec<&str> = results.iter().map(|r| r.id.group.as_str()).collect();
+    groups.dedup();
+    let steps = distinct(results.iter().map(|r| r.id.step as f64).collect());
+    // Each step gets a cluster of bars, one per group, that takes up 80% of the space
+    let bar_width = 0.8 / groups.len() as f64;
+
+    let mut fg = Figure::new();
+    fg.set_enhanced_text(true);
+    let ticks: Vec<Tick<_, String>> = steps
         .iter()
-        .map(|&eiat| get_mean_server_runtime(SCHNORR_STR, 1, eiat).unwrap() / 1_000_000_000f64)
+        .map(|&step| Tick::Major(step, Fix(format!("Step {}", step))))
         .collect();
+    let plot = fg.axes2d();
+    plot.set_legend(
+        Coordinate::Graph(0.98),
+        Coordinate::Graph(0.98),
+        &[Placement(AlignRight, AlignTop)],
+        &[],
+    )
+    .set_x_ticks_custom(ticks, &[], &[])
+    .set_x_range(Fix(steps[0] - 0.5), Fix(steps[steps.len() - 1] + 0.5))
+    .set_y_label(&format!("Time for {} session(s) (μs)", num_sessions), &[])
+    .set_y_range(Fix(0f64), Auto);
+
+    for (group_idx, group) in groups.iter().enumerate() {
+        let (color, _, _) = line_style(group, group_idx);
+        // Where this group's bar sits within each cluster
+        let offset = (group_idx as f64 + 0.5) * bar_width - 0.4;
+        let bars: Vec<_> = results.iter().filter(|r| r.id.group == *group).collect();
+
+        let xs: Vec<f64> = bars.iter().map(|r| r.id.step as f64 + offset).collect();
+        let estimates = bars
+            .iter()
+            .map(|r| r.estimates())
+            .collect::<Result<Vec<_>, _>>()?;
+        let micros = |ns: f64| ns / 1000f64;
+        let means: Vec<f64> = estimates.iter().map(|e| micros(e.mean)).collect();
+        let ci_lows: Vec<f64> = estimates.iter().map(|e| micros(e.mean_lower)).collect();
+        let ci_highs: Vec<f64> = estimates.iter().map(|e| micros(e.mean_upper)).collect();
+
+        plot.boxes_set_width(
+            &xs,
+            &means,
+            vec![bar_width; xs.len()],
+            &[
+                Caption(group),
+                Color(color),
+                BorderColor("black"),
+                FillAlpha(0.7),
+            ],
+        );
+        interval_bars(plot, &xs, &ci_lows, &ci_highs, "black");
+    }
+
+    out.save(&mut fg, "step_costs")
+}
+
+/// Plots the latency CDF of every load generator report in the directory
+fn plot_latency_cdfs(loadgen_dir: &Path, out: &Output) -> Result<(), Box<dyn Error>> {
+    let mut reports: Vec<(PathBuf, LoadReport)> = Vec::new();
+    for entry in fs::read_dir(loadgen_dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        match serde_json::from_str::<LoadReport>(&fs::read_to_string(&path)?) {
+            Ok(report) if !report.latency_cdf.is_empty() => reports.push((path, report)),
+            _ => println!("{} isn't a load generator report, skipping", path.display()),
+        }
+    }
+    if reports.is_empty() {
+        println!(
+            "no load generator reports in {}, skipping",
+            loadgen_dir.display()
+        );
+        return Ok(());
+    }
+    reports.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut fg = Figure::new();
+    fg.set_enhanced_text(true);
+    let plot = fg.axes2d();
+    plot.set_legend(
+        Coordinate::Graph(0.98),
+        Coordinate::Graph(0.02),
+        &[Placement(AlignRight, AlignBottom)],
+        &[],
+    )
+    .set_x_log(Some(10f64))
+    .set_x_label("Session latency (ms)", &[])
+    .set_y_label("Fraction of sessions", &[])
+    .set_y_range(Fix(0f64), Fix(1f64));
+
+    for (i, (_, report)) in reports.iter().enumerate() {
+        let group = report.server_backend.group_name(report.scheme);
+        let (color, dash_type, _) = line_style(&group, i);
+        let line_name = format!(
+            "{}-thread {} at {} sessions/s",
+            report.pool_size, group, report.arrival_rate
+        );
+
+        // Latencies can't be 0 on a log scale, and never are in practice
+        let (xs, ys): (Vec<f64>, Vec<f64>) = report
+            .latency_cdf
+            .iter()
+            .filter(|p| p.latency_ms > 0.0)
+            .map(|p| (p.latency_ms, p.quantile))
+            .unzip();
+        plot.lines(
+            xs,
+            ys,
+            &[Caption(&line_name), Color(color), LineStyle(dash_type)],
+        );
+    }
+
+    out.save(&mut fg, "latency_cdf")
+}
+
+/// Parses the value of the given flag, or exits with an explanation
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T
+where
+    T::Err: std::fmt::Display,
+{
+    let value = value.unwrap_or_else(|| {
+        eprintln!("{} needs a value\n\n{}", flag, USAGE);
+        exit(2)
+    });
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("invalid value {:?} for {}: {}", value, flag, e);
+        exit(2)
+    })
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut criterion_dir = PathBuf::from("./ec2_data/webserver_bench");
+    let mut piecewise_dir = PathBuf::from("./ec2_data/piecewise_bench");
+    let mut loadgen_dir: Option<PathBuf> = None;
+    let mut out_dir = PathBuf::from("plots");
+    let mut out_path: Option<PathBuf> = None;
+    let mut format: Option<Format> = None;
+    let mut size = (560, 350);
+    let mut scenario_path = std::env::var(SCENARIO_VAR).ok();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--criterion-dir" => criterion_dir = parse_value(&flag, args.next()),
+            "--piecewise-dir" => piecewise_dir = parse_value(&flag, args.next()),
+            "--loadgen-dir" => loadgen_dir = Some(parse_value(&flag, args.next())),
+            "--out-dir" => out_dir = parse_value(&flag, args.next()),
+            "--out" => out_path = Some(parse_value(&flag, args.next())),
+            "--format" => format = Some(parse_value(&flag, args.next())),
+            "--size" => {
+                let value: String = parse_value(&flag, args.next());
+                let parsed = value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+                size = parsed.unwrap_or_else(|| {
+                    eprintln!("invalid value {:?} for --size, expected WxH", value);
+                    exit(2)
+                });
+            }
+            "--scenario" => scenario_path = Some(parse_value(&flag, args.next())),
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => {
+                eprintln!("unknown option {:?}\n\n{}", flag, USAGE);
+                exit(2)
+            }
+        }
+    }
+    // Without a --format, --out's extension says which one it wants
+    let format = format.unwrap_or_else(|| {
+        let ext = out_path.as_ref().and_then(|path| path.extension());
+        match ext.and_then(|ext| ext.to_str()) {
+            Some("svg") => Format::Svg,
+            Some("png") => Format::Png,
+            Some(ext) => {
+                eprintln!("unsupported format {:?}, expected svg or png", ext);
+                exit(2)
+            }
+            None => Format::Both,
+        }
+    });
+    fs::create_dir_all(&out_dir)?;
+    if let Some(dir) = out_path.as_ref().and_then(|path| path.parent()) {
+        fs::create_dir_all(dir)?;
+    }
+    let out = Output {
+        dir: out_dir,
+        runtime_path: out_path,
+        format,
+        size,
+    };
+
+    let mut results = if criterion_dir.is_dir() {
+        discover(&criterion_dir)?
+    } else {
+        Vec::new()
+    };
+    if let Some(path) = scenario_path {
+        let scenario = Scenario::load(path)?;
+        results.retain(|r| in_scenario(&scenario, r));
+    }
+    if results.is_empty() {
+        println!(
+            "no webserver benchmarks in {}, skipping",
+            criterion_dir.display()
+        );
+    } else {
+        plot_runtime(&results, &out)?;
+        plot_throughput(&results, &out)?;
+    }
+
+    if piecewise_dir.is_dir() {
+        plot_step_costs(&piecewise_dir, &out)?;
+    }
+    if let Some(dir) = loadgen_dir {
+        plot_latency_cdfs(&dir, &out)?;
+    }
 
-    // Plot Schnorr data. This is a blue solid line with square points.
-    let line_name = format!("1-thread {}", SCHNORR_STR);
-    plot.lines_points(
-        &workload_factors,
-        schnorr_runtimes,
-        &[
-            Caption(&line_name),
-            Color("blue"),
-            PointSymbol('S'),
-            LineStyle(DashType::Solid),
-        ],
-    );
-
-    //fg.show().unwrap();
-    fg.save_to_svg("plots/server_runtime.svg", 560, 350)
-        .unwrap();
-
-    // And we can draw something in the drawing area
     Ok(())
 }
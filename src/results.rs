@@ -0,0 +1,329 @@
+/*
+Reading webserver benchmark results back out of a Criterion output directory. Criterion lays them
+out as
+
+    <dir>/<group name>/<bench name>/new/estimates.json
+
+where the group name is the scheme's, and the bench name comes from Scenario::bench_name. The names
+are all we need to tell which scheme, pool size, and workload a result is for, so any directory of
+results can be read without knowing ahead of time what's in it.
+*/
+
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Which webserver benchmark a result is for
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchId {
+    /// The benchmark group, e.g., "Parallel Abe"
+    pub group: String,
+    pub pool_size: usize,
+    pub num_clients: usize,
+    /// Expected interarrival time, in milliseconds
+    pub eiat_ms: f64,
+}
+
+impl BenchId {
+    /// Parses a bench name of the form made by `Scenario::bench_name`. Returns `None` if it isn't
+    /// one.
+    pub fn parse(group: &str, bench_name: &str) -> Option<BenchId> {
+        let (pool_size, rest) = bench_name.split_once("-core server handling ")?;
+        let (num_clients, rest) = rest.split_once(" clients at ")?;
+        let eiat_ms = rest.strip_suffix("ms EIAT")?;
+
+        Some(BenchId {
+            group: group.to_string(),
+            pool_size: pool_size.parse().ok()?,
+            num_clients: num_clients.parse().ok()?,
+            eiat_ms: eiat_ms.parse().ok()?,
+        })
+    }
+
+    /// The workload factor is 1 / expected interarrival time
+    pub fn workload_factor(&self) -> f64 {
+        self.eiat_ms.recip()
+    }
+}
+
+/// Which step of the protocol a piecewise benchmark result is for
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StepId {
+    /// The benchmark group, e.g., "Parallel Abe"
+    pub group: String,
+    /// The step of the protocol, from 1 to 4
+    pub step: usize,
+    /// How many sessions ran the step at once
+    pub num_sessions: usize,
+}
+
+impl StepId {
+    /// Parses a bench name of the form "step 1[s = 16]", as made by bench_piecewise.rs. Returns
+    /// `None` if it isn't one.
+    pub fn parse(group: &str, bench_name: &str) -> Option<StepId> {
+        let rest = bench_name.strip_prefix("step ")?;
+        let (step, rest) = rest.split_once("[s = ")?;
+        let num_sessions = rest.strip_suffix(']')?;
+
+        Some(StepId {
+            group: group.to_string(),
+            step: step.parse().ok()?,
+            num_sessions: num_sessions.parse().ok()?,
+        })
+    }
+}
+
+/// Criterion's estimates for a benchmark, in nanoseconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimates {
+    pub mean: f64,
+    /// The confidence interval on the mean
+    pub mean_lower: f64,
+    pub mean_upper: f64,
+    /// How confident Criterion is that the mean is in the interval, e.g., 0.95
+    pub confidence_level: f64,
+    /// The standard deviation of the samples
+    pub std_dev: f64,
+}
+
+impl Estimates {
+    /// Reads the estimates out of a Criterion estimates.json
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Estimates, Box<dyn Error>> {
+        // We do this weird structure bc that's how criterion formats its JSON outputs
+        #[derive(Deserialize)]
+        struct ConfidenceInterval {
+            confidence_level: f64,
+            lower_bound: f64,
+            upper_bound: f64,
+        }
+        #[derive(Deserialize)]
+        struct Estimate {
+            confidence_interval: ConfidenceInterval,
+            point_estimate: f64,
+        }
+        #[derive(Deserialize)]
+        struct EstimatesFile {
+            mean: Estimate,
+            std_dev: Estimate,
+        }
+
+        let file = File::open(path)?;
+        let estimates: EstimatesFile = serde_json::from_reader(BufReader::new(file))?;
+        let ci = &estimates.mean.confidence_interval;
+        Ok(Estimates {
+            mean: estimates.mean.point_estimate,
+            mean_lower: ci.lower_bound,
+            mean_upper: ci.upper_bound,
+            confidence_level: ci.confidence_level,
+            std_dev: estimates.std_dev.point_estimate,
+        })
+    }
+
+    /// Writes the estimates out the way Criterion does, so they can be read back by anything that
+    /// reads Criterion's output. Only the fields `read` looks at are written.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let estimate = |point: f64, lower: f64, upper: f64| {
+            serde_json::json!({
+                "confidence_interval": {
+                    "confidence_level": self.confidence_level,
+                    "lower_bound": lower,
+                    "upper_bound": upper,
+                },
+                "point_estimate": point,
+            })
+        };
+        let estimates = serde_json::json!({
+            "mean": estimate(self.mean, self.mean_lower, self.mean_upper),
+            "std_dev": estimate(self.std_dev, self.std_dev, self.std_dev),
+        });
+
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        serde_json::to_writer(File::create(path)?, &estimates)?;
+        Ok(())
+    }
+
+    /// Applies a decreasing function, like n / t, to the estimates. Returns the new mean, and the
+    /// lower and upper ends of the new confidence interval.
+    pub fn map_decreasing<F: Fn(f64) -> f64>(&self, f: F) -> (f64, f64, f64) {
+        (f(self.mean), f(self.mean_upper), f(self.mean_lower))
+    }
+}
+
+/// A webserver benchmark result in a Criterion output directory
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub id: BenchId,
+    /// Where the result's estimates.json is
+    pub estimates_path: PathBuf,
+}
+
+impl BenchResult {
+    pub fn estimates(&self) -> Result<Estimates, Box<dyn Error>> {
+        Estimates::read(&self.estimates_path)
+    }
+}
+
+/// A piecewise benchmark result in a Criterion output directory
+#[derive(Clone, Debug)]
+pub struct StepResult {
+    pub id: StepId,
+    /// Where the result's estimates.json is
+    pub estimates_path: PathBuf,
+}
+
+impl StepResult {
+    pub fn estimates(&self) -> Result<Estimates, Box<dyn Error>> {
+        Estimates::read(&self.estimates_path)
+    }
+}
+
+/// Finds every benchmark in the given Criterion output directory whose group and bench name
+/// `parse` recognizes, and that has estimates. Returns what `parse` made of each one, along with
+/// the path to its estimates.
+fn discover_with<I, F>(dir: &Path, parse: F) -> Result<Vec<(I, PathBuf)>, Box<dyn Error>>
+where
+    F: Fn(&str, &str) -> Option<I>,
+{
+    let mut found = Vec::new();
+
+    for group_dir in fs::read_dir(dir)? {
+        let group_dir = group_dir?.path();
+        let group = match group_dir.file_name().and_then(|name| name.to_str()) {
+            Some(group) if group_dir.is_dir() => group.to_string(),
+            _ => continue,
+        };
+
+        for bench_dir in fs::read_dir(&group_dir)? {
+            let bench_dir = bench_dir?.path();
+            let id = match bench_dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| parse(&group, name))
+            {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let estimates_path = bench_dir.join("new").join("estimates.json");
+            if estimates_path.is_file() {
+                found.push((id, estimates_path));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Finds every webserver benchmark result in the given Criterion output directory. Anything that
+/// isn't one, like Criterion's reports or other benchmarks, is skipped. The results are sorted by
+/// group, then pool size, then number of clients, then interarrival time.
+pub fn discover<P: AsRef<Path>>(dir: P) -> Result<Vec<BenchResult>, Box<dyn Error>> {
+    let mut results: Vec<BenchResult> = discover_with(dir.as_ref(), BenchId::parse)?
+        .into_iter()
+        .map(|(id, estimates_path)| BenchResult { id, estimates_path })
+        .collect();
+
+    results.sort_by(|a, b| {
+        let key = |id: &BenchId| (id.group.clone(), id.pool_size, id.num_clients);
+        key(&a.id)
+            .cmp(&key(&b.id))
+            .then(a.id.eiat_ms.total_cmp(&b.id.eiat_ms))
+    });
+    Ok(results)
+}
+
+/// Finds every piecewise benchmark result in the given Criterion output directory. The results are
+/// sorted by group, then step, then number of sessions.
+pub fn discover_steps<P: AsRef<Path>>(dir: P) -> Result<Vec<StepResult>, Box<dyn Error>> {
+    let mut results: Vec<StepResult> = discover_with(dir.as_ref(), StepId::parse)?
+        .into_iter()
+        .map(|(id, estimates_path)| StepResult { id, estimates_path })
+        .collect();
+
+    results.sort_by(|a, b| {
+        let key = |id: &StepId| (id.group.clone(), id.step, id.num_sessions);
+        key(&a.id).cmp(&key(&b.id))
+    });
+    Ok(results)
+}
+
+#[test]
+fn test_parse_bench_name() {
+    use crate::scenario::Scenario;
+
+    let scenario = Scenario::default();
+    for &eiat in &[1.0, 2.5, 130.0] {
+        let id = BenchId::parse("Parallel Abe", &scenario.bench_name(16, eiat)).unwrap();
+        assert_eq!(id.pool_size, 16);
+        assert_eq!(id.num_clients, scenario.num_clients);
+        assert_eq!(id.eiat_ms, eiat);
+    }
+
+    assert!(BenchId::parse("Parallel Abe", "step 1[s = 16]").is_none());
+    assert!(BenchId::parse("Parallel Abe", "report").is_none());
+}
+
+// Everything in the EC2 data is found, and nothing else is
+#[test]
+fn test_discover() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/ec2_data/webserver_bench");
+    let results = discover(dir).unwrap();
+
+    // Abe ran on 3 pool sizes, and Schnorr on 1, each at 5 interarrival times
+    assert_eq!(results.len(), 4 * 5);
+    assert!(results.iter().all(|r| r.id.num_clients == 100));
+    assert_eq!(results[0].id.group, "Parallel Abe");
+    assert_eq!(results[0].id.pool_size, 1);
+    assert_eq!(results[0].id.eiat_ms, 1.0);
+    let estimates = results[0].estimates().unwrap();
+    assert!(estimates.mean_lower <= estimates.mean && estimates.mean <= estimates.mean_upper);
+    assert_eq!(estimates.confidence_level, 0.95);
+    assert!(estimates.std_dev > 0.0);
+
+    // Webserver and piecewise results don't get mixed up
+    assert!(discover_steps(dir).unwrap().is_empty());
+    assert!(discover(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/ec2_data/piecewise_bench"
+    ))
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn test_discover_steps() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/ec2_data/piecewise_bench");
+    let results = discover_steps(dir).unwrap();
+
+    // 2 schemes, 4 steps, 6 numbers of sessions. Keygen isn't a step.
+    assert_eq!(results.len(), 2 * 4 * 6);
+    let id = &results[0].id;
+    assert_eq!(
+        (id.group.as_str(), id.step, id.num_sessions),
+        ("Parallel Abe", 1, 1)
+    );
+    let id = &results[5].id;
+    assert_eq!((id.step, id.num_sessions), (1, 32));
+
+    assert!(StepId::parse("Parallel Abe", "keygen").is_none());
+}
+
+#[test]
+fn test_map_decreasing() {
+    let estimates = Estimates {
+        mean: 2.0,
+        mean_lower: 1.0,
+        mean_upper: 4.0,
+        confidence_level: 0.95,
+        std_dev: 1.0,
+    };
+    // 1/t flips the interval around, to [0.25, 1]
+    assert_eq!(estimates.map_decreasing(f64::recip), (0.5, 0.25, 1.0));
+}
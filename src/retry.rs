@@ -0,0 +1,105 @@
+use crate::error::Error;
+use std::time::Duration;
+
+use rand::Rng;
+
+/// How a client retries a message that the server turned away, or that never got to the server.
+/// The wait between attempts grows exponentially, and is jittered so that clients that were
+/// turned away together don't all come back together.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The wait before the first retry
+    pub initial_backoff: Duration,
+    /// The wait is multiplied by this after every retry
+    pub multiplier: f64,
+    /// The wait never grows past this
+    pub max_backoff: Duration,
+    /// The most times a single message is sent, counting the first time
+    pub max_attempts: u32,
+    /// The longest a client spends on a whole session before it gives up. `None` means there's no
+    /// limit.
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    /// Starts out at 75ms and doubles up to 2s, for at most 20 attempts per message and a minute
+    /// per session
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(75),
+            multiplier: 2.0,
+            max_backoff: Duration::from_secs(2),
+            max_attempts: 20,
+            deadline: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns whether a message that failed with the given error is worth sending again. That's
+    /// the case if the server was busy, or if it couldn't be reached at all.
+    pub fn is_retryable(err: &Error) -> bool {
+        matches!(err, Error::CapacityExceeded | Error::ConnectionFailed(_))
+    }
+
+    /// Returns how long to wait before the given retry, counting from 0. For a backoff of b, this
+    /// is uniform in [b/2, b]. b is kept between 0 and the cap, whatever the multiplier is.
+    pub fn backoff<R: Rng>(&self, rng: &mut R, retry: u32) -> Duration {
+        // This saturates to infinity rather than overflowing, and then the cap kicks in. A NaN
+        // multiplier makes it NaN, which f64::min also turns into the cap, and a negative one can
+        // make it negative, which f64::max turns into 0.
+        let growth = self.multiplier.powi(retry.min(i32::MAX as u32) as i32);
+        let b = (self.initial_backoff.as_secs_f64() * growth)
+            .min(self.max_backoff.as_secs_f64())
+            .max(0.0);
+
+        Duration::from_secs_f64(b / 2.0 + rng.gen::<f64>() * b / 2.0)
+    }
+}
+
+/// What a client went through to get its signature
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClientStats {
+    /// How many times a message had to be sent again
+    pub num_retries: u32,
+    /// How long the client spent waiting between retries
+    pub backoff_time: Duration,
+    /// How long the whole session took, including backoff
+    pub total_time: Duration,
+}
+
+#[test]
+fn test_backoff() {
+    let mut csprng = rand::thread_rng();
+    let policy = RetryPolicy {
+        initial_backoff: Duration::from_millis(100),
+        multiplier: 2.0,
+        max_backoff: Duration::from_millis(1000),
+        max_attempts: 10,
+        deadline: None,
+    };
+
+    // The backoff doubles every time until it hits the cap
+    let expected_caps = [100, 200, 400, 800, 1000, 1000];
+    for (retry, &cap) in expected_caps.iter().enumerate() {
+        let cap = Duration::from_millis(cap);
+        for _ in 0..100 {
+            let b = policy.backoff(&mut csprng, retry as u32);
+            assert!(cap / 2 <= b && b <= cap);
+        }
+    }
+
+    // A huge number of retries shouldn't overflow anything
+    assert!(policy.backoff(&mut csprng, u32::MAX) <= policy.max_backoff);
+
+    // Nor should a nonsense multiplier
+    for multiplier in [-2.0, f64::NAN, f64::INFINITY] {
+        let policy = RetryPolicy {
+            multiplier,
+            ..policy.clone()
+        };
+        for retry in 0..4 {
+            assert!(policy.backoff(&mut csprng, retry) <= policy.max_backoff);
+        }
+    }
+}
@@ -0,0 +1,540 @@
+/*
+A scenario is a parameter sweep for the webserver benchmark: which schemes to run, on how many
+server threads, under which workloads. bench_all.rs runs whatever scenario it's given, and the
+plotter reads the same one to know what to look for, so the two can't drift apart.
+
+Scenarios are TOML or JSON files, depending on the extension. scenarios/ec2.toml is the sweep that
+produced the data in ec2_data, and is also what you get if you don't pick one.
+*/
+
+use crate::{
+    latency::{Delay, Network, NetworkModel},
+    seed::SEED_VAR,
+};
+use std::{error::Error, fs, path::Path, str::FromStr, time::Duration};
+
+use rand::Rng;
+use rand_distr::{Distribution, Exp, Normal};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// The environment variable that points the benchmark and the plotter at a scenario file
+pub const SCENARIO_VAR: &str = "BENCH_SCENARIO";
+
+/// The environment variable that picks the server backend, overriding the scenario's
+pub const BACKEND_VAR: &str = "BENCH_BACKEND";
+
+/// Appended to a scheme's group name when it's run on the async server, so its results don't
+/// overwrite the thread pool's
+pub const ASYNC_GROUP_SUFFIX: &str = " (async)";
+
+/// The blind signature schemes that can be benchmarked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scheme {
+    BlindSchnorr,
+    ClauseBlindSchnorr,
+    Abe,
+    BlindRsa,
+    BlindBls,
+}
+
+impl Scheme {
+    pub const ALL: &'static [Scheme] = &[
+        Scheme::BlindSchnorr,
+        Scheme::ClauseBlindSchnorr,
+        Scheme::Abe,
+        Scheme::BlindRsa,
+        Scheme::BlindBls,
+    ];
+
+    /// The name this scheme goes by on the command line, in scenario files, and in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scheme::BlindSchnorr => "blind-schnorr",
+            Scheme::ClauseBlindSchnorr => "clause-blind-schnorr",
+            Scheme::Abe => "abe",
+            Scheme::BlindRsa => "blind-rsa",
+            Scheme::BlindBls => "blind-bls",
+        }
+    }
+
+    /// The name of this scheme's benchmark group. This is also the name of the directory Criterion
+    /// puts the results in.
+    pub fn group_name(&self) -> &'static str {
+        match self {
+            Scheme::BlindSchnorr => "Sequential Blind Schnorr",
+            Scheme::ClauseBlindSchnorr => "Parallel Clause Blind Schnorr",
+            Scheme::Abe => "Parallel Abe",
+            Scheme::BlindRsa => "Parallel Blind RSA",
+            Scheme::BlindBls => "Parallel Blind BLS",
+        }
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Scheme, String> {
+        Scheme::ALL
+            .iter()
+            .find(|scheme| scheme.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = Scheme::ALL.iter().map(Scheme::name).collect();
+                format!(
+                    "unknown scheme {:?}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// How the HTTP server handles requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServerBackend {
+    /// rouille, with a thread per request in flight. A request keeps its thread through the
+    /// latency sleep. This is what produced the EC2 data.
+    #[default]
+    ThreadPool,
+    /// tokio and hyper. The crypto runs on a blocking pool with as many threads, and the latency
+    /// sleep is a timer that doesn't hold on to one.
+    Async,
+}
+
+impl ServerBackend {
+    pub const ALL: &'static [ServerBackend] = &[ServerBackend::ThreadPool, ServerBackend::Async];
+
+    /// The name this backend goes by on the command line, in scenario files, and in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerBackend::ThreadPool => "thread-pool",
+            ServerBackend::Async => "async",
+        }
+    }
+
+    /// The name of the given scheme's benchmark group on this backend. This is the scheme's own
+    /// group name, marked if the backend is async.
+    pub fn group_name(&self, scheme: Scheme) -> String {
+        match self {
+            ServerBackend::ThreadPool => scheme.group_name().to_string(),
+            ServerBackend::Async => format!("{}{}", scheme.group_name(), ASYNC_GROUP_SUFFIX),
+        }
+    }
+}
+
+impl FromStr for ServerBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ServerBackend, String> {
+        ServerBackend::ALL
+            .iter()
+            .find(|backend| backend.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = ServerBackend::ALL.iter().map(ServerBackend::name).collect();
+                format!(
+                    "unknown server backend {:?}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// How the time between one client arriving and the next is distributed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArrivalProcess {
+    /// Clients arrive as a Poisson point process, so interarrival times are exponential
+    Poisson,
+    /// Clients arrive exactly one interarrival time apart
+    Constant,
+}
+
+impl ArrivalProcess {
+    /// Samples the time until the next client arrives, given the mean time between arrivals
+    pub fn sample<R: Rng>(&self, rng: &mut R, mean_interarrival: Duration) -> Duration {
+        match self {
+            ArrivalProcess::Poisson => {
+                // Interarrival times of a Poisson process with rate λ are exponential with
+                // parameter λ = 1 / mean
+                let distr = Exp::new(mean_interarrival.as_secs_f64().recip()).unwrap();
+                Duration::from_secs_f64(distr.sample(rng))
+            }
+            ArrivalProcess::Constant => mean_interarrival,
+        }
+    }
+}
+
+/// How long messages take to get between the clients and the server
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "kebab-case")]
+pub enum LatencyModel {
+    /// The server sleeps for a normally distributed time, in milliseconds, after computing each
+    /// response. Negative samples count as 0. This is what produced the EC2 data, and it ties up a
+    /// server thread for the whole delay.
+    Normal { mean_ms: f64, std_ms: f64 },
+    /// Messages are delayed on the client's side of the transport, in both directions, according
+    /// to a network model. The server doesn't sleep at all.
+    Network(NetworkModel),
+}
+
+impl LatencyModel {
+    /// Returns the distribution of the server's sleep after each response, in milliseconds. Fails if
+    /// the mean or standard deviation isn't a finite number, or the standard deviation is negative.
+    pub fn server_distr(&self) -> Result<Normal<f64>, Box<dyn Error>> {
+        match self {
+            LatencyModel::Normal { mean_ms, std_ms } => {
+                Delay::Normal {
+                    mean_ms: *mean_ms,
+                    std_ms: *std_ms,
+                }
+                .check()?;
+                // check has ruled out everything Normal::new rejects
+                Ok(Normal::new(*mean_ms, *std_ms).unwrap())
+            }
+            LatencyModel::Network(_) => Ok(Normal::new(0.0, 0.0).unwrap()),
+        }
+    }
+
+    /// Returns the network that clients connect through. Loads the RTT trace if there is one.
+    pub fn network(&self) -> Result<Network, Box<dyn Error>> {
+        match self {
+            LatencyModel::Normal { .. } => Ok(Network::instant()),
+            LatencyModel::Network(model) => Network::new(model.clone()),
+        }
+    }
+}
+
+/// Reads anything in a scenario, or a whole scenario, from a file. Files ending in .toml are read
+/// as TOML, and anything else as JSON.
+pub fn load_file<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T, Box<dyn Error>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        Ok(toml::from_str(&contents)?)
+    } else {
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+/// A scheme, and every server thread count to run it with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchemeRun {
+    pub scheme: Scheme,
+    pub pool_sizes: Vec<usize>,
+}
+
+/// A full parameter sweep. Every scheme is run with every one of its pool sizes, at every
+/// interarrival time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Where the HTTP server listens
+    pub server_addr: String,
+    /// Number of clients that connect to the server within a benchmark
+    pub num_clients: usize,
+    pub arrival_process: ArrivalProcess,
+    /// Mean times between clients arriving, in milliseconds
+    pub interarrival_times_ms: Vec<f64>,
+    pub latency: LatencyModel,
+    /// The server forgets about a session if its client goes quiet for this many seconds
+    pub session_timeout_s: u64,
+    pub schemes: Vec<SchemeRun>,
+    /// Seeds the arrival schedule and the latency model, so every run gets the same workload. If
+    /// this is missing, every run gets a different one.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// How the HTTP server handles requests. Defaults to the thread pool.
+    #[serde(default)]
+    pub server_backend: ServerBackend,
+}
+
+impl Default for Scenario {
+    /// The sweep in scenarios/ec2.toml
+    fn default() -> Scenario {
+        let parallel = |scheme| SchemeRun {
+            scheme,
+            pool_sizes: vec![1, 4, 16],
+        };
+
+        Scenario {
+            server_addr: "localhost:14147".to_string(),
+            num_clients: 100,
+            arrival_process: ArrivalProcess::Poisson,
+            interarrival_times_ms: vec![1.0, 10.0, 50.0, 90.0, 130.0],
+            // 30ms mean latency between server and client (this is roughly what I get on a WiFi
+            // network between NYC and msu.edu ). Let's say this is normally distributed with
+            // standard deviation of 5ms so that 95% of connections have latency between 20ms and
+            // 40ms.
+            latency: LatencyModel::Normal {
+                mean_ms: 30.0,
+                std_ms: 5.0,
+            },
+            session_timeout_s: 30,
+            schemes: vec![
+                parallel(Scheme::Abe),
+                // Schnorr is sequential so the threadpool size is always 1
+                SchemeRun {
+                    scheme: Scheme::BlindSchnorr,
+                    pool_sizes: vec![1],
+                },
+                parallel(Scheme::ClauseBlindSchnorr),
+                parallel(Scheme::BlindRsa),
+                parallel(Scheme::BlindBls),
+            ],
+            seed: None,
+            server_backend: ServerBackend::ThreadPool,
+        }
+    }
+}
+
+impl Scenario {
+    /// Reads a scenario from a file. Files ending in .toml are read as TOML, and anything else as
+    /// JSON. Fails if the scenario doesn't pass `check`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, Box<dyn Error>> {
+        let scenario: Scenario = load_file(path)?;
+        scenario.check()?;
+        Ok(scenario)
+    }
+
+    /// Checks that every benchmark in the sweep can run, and that there's at least one. The error
+    /// names the field that's wrong.
+    fn check(&self) -> Result<(), String> {
+        if self.num_clients == 0 {
+            return Err("num_clients must be positive".to_string());
+        }
+        if self.interarrival_times_ms.is_empty() {
+            return Err("interarrival_times_ms is empty".to_string());
+        }
+        // An interarrival time that rounds down to 0ns has every client arrive at once
+        for &iat in &self.interarrival_times_ms {
+            let positive = iat.is_finite() && iat > 0.0;
+            if !positive || Duration::from_secs_f64(iat / 1000.0).is_zero() {
+                return Err(format!("interarrival_times_ms has a bad entry, {}", iat));
+            }
+        }
+        if self.session_timeout_s == 0 {
+            return Err("session_timeout_s must be positive".to_string());
+        }
+        if self.schemes.is_empty() {
+            return Err("schemes is empty".to_string());
+        }
+        for run in &self.schemes {
+            if run.pool_sizes.is_empty() {
+                return Err(format!("pool_sizes for {} is empty", run.scheme.name()));
+            }
+            if run.pool_sizes.contains(&0) {
+                return Err(format!("pool_sizes for {} has a 0", run.scheme.name()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the scenario file named by `BENCH_SCENARIO`, or returns the default scenario if it's
+    /// unset. If `BENCH_SEED` or `BENCH_BACKEND` is set, it replaces the scenario's seed or server
+    /// backend.
+    pub fn from_env() -> Result<Scenario, Box<dyn Error>> {
+        let mut scenario = match std::env::var_os(SCENARIO_VAR) {
+            Some(path) => Scenario::load(path)?,
+            None => Scenario::default(),
+        };
+        if let Ok(seed) = std::env::var(SEED_VAR) {
+            let seed = seed
+                .parse()
+                .map_err(|e| format!("invalid {} {:?}: {}", SEED_VAR, seed, e))?;
+            scenario.seed = Some(seed);
+        }
+        if let Ok(backend) = std::env::var(BACKEND_VAR) {
+            scenario.server_backend = backend
+                .parse()
+                .map_err(|e| format!("invalid {}: {}", BACKEND_VAR, e))?;
+        }
+
+        Ok(scenario)
+    }
+
+    pub fn session_timeout(&self) -> Duration {
+        Duration::from_secs(self.session_timeout_s)
+    }
+
+    /// The name of the given scheme's benchmark group on this scenario's server backend
+    pub fn group_name(&self, scheme: Scheme) -> String {
+        self.server_backend.group_name(scheme)
+    }
+
+    /// The name of the benchmark for the given pool size and mean interarrival time. Together with
+    /// the scheme's group name, this is where Criterion puts the results.
+    pub fn bench_name(&self, pool_size: usize, interarrival_time_ms: f64) -> String {
+        format!(
+            "{}-core server handling {} clients at {}ms EIAT",
+            pool_size, self.num_clients, interarrival_time_ms
+        )
+    }
+}
+
+// The scenario file that ships with the repo is the default one, and names benchmarks the way
+// the EC2 data does
+#[test]
+fn test_default_scenario() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/ec2.toml");
+    let scenario = Scenario::load(path).unwrap();
+    assert_eq!(scenario, Scenario::default());
+
+    let json = serde_json::to_string(&scenario).unwrap();
+    assert_eq!(serde_json::from_str::<Scenario>(&json).unwrap(), scenario);
+
+    let dir = format!(
+        "{}/ec2_data/webserver_bench/{}/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        Scheme::Abe.group_name(),
+        scenario.bench_name(4, 10.0)
+    );
+    assert!(Path::new(&dir).is_dir());
+}
+
+// A session timeout of zero is turned away when the scenario is loaded
+#[test]
+fn test_zero_session_timeout() {
+    let no_timeout = Scenario {
+        session_timeout_s: 0,
+        ..Scenario::default()
+    };
+    let path = std::env::temp_dir().join(format!(
+        "blind_sig_bench_scenario_{}.json",
+        std::process::id()
+    ));
+    fs::write(&path, serde_json::to_string(&no_timeout).unwrap()).unwrap();
+    let res = Scenario::load(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(res.is_err());
+}
+
+// Each of these would crash the benchmark, or quietly run nothing
+#[cfg(test)]
+fn assert_rejected(scenario: Scenario, field: &str) {
+    let err = scenario.check().unwrap_err();
+    assert!(err.contains(field), "{:?} doesn't mention {}", err, field);
+}
+
+#[test]
+fn test_check_num_clients() {
+    let scenario = Scenario {
+        num_clients: 0,
+        ..Scenario::default()
+    };
+    assert_rejected(scenario, "num_clients");
+}
+
+#[test]
+fn test_check_interarrival_times() {
+    assert!(Scenario::default().check().is_ok());
+    for iats in [
+        vec![],
+        vec![10.0, -1.0],
+        vec![0.0],
+        vec![1e-10],
+        vec![f64::NAN],
+    ] {
+        let scenario = Scenario {
+            interarrival_times_ms: iats,
+            ..Scenario::default()
+        };
+        assert_rejected(scenario, "interarrival_times_ms");
+    }
+}
+
+#[test]
+fn test_check_schemes() {
+    let scenario = Scenario {
+        schemes: vec![],
+        ..Scenario::default()
+    };
+    assert_rejected(scenario, "schemes");
+}
+
+#[test]
+fn test_check_pool_sizes() {
+    for pool_sizes in [vec![], vec![1, 0]] {
+        let scenario = Scenario {
+            schemes: vec![SchemeRun {
+                scheme: Scheme::Abe,
+                pool_sizes,
+            }],
+            ..Scenario::default()
+        };
+        assert_rejected(scenario, "pool_sizes");
+    }
+}
+
+#[test]
+fn test_scheme_names() {
+    for scheme in Scheme::ALL {
+        assert_eq!(scheme.name().parse::<Scheme>(), Ok(*scheme));
+    }
+    assert!("schnorr".parse::<Scheme>().is_err());
+
+    for backend in ServerBackend::ALL {
+        assert_eq!(backend.name().parse::<ServerBackend>(), Ok(*backend));
+    }
+    assert!("tokio".parse::<ServerBackend>().is_err());
+}
+
+// A network latency model can be written in either format, leaving out whatever it doesn't need
+#[test]
+fn test_network_latency_model() {
+    use crate::latency::RttSource;
+
+    let toml_model = r#"
+        model = "network"
+        rtt = { source = "per-client", distr = "normal", mean_ms = 60.0, std_ms = 10.0 }
+        downlink_bandwidth = 125000.0
+        loss_rate = 0.01
+    "#;
+    let json_model = r#"{
+        "model": "network",
+        "rtt": { "source": "per-client", "distr": "normal", "mean_ms": 60.0, "std_ms": 10.0 },
+        "downlink_bandwidth": 125000.0,
+        "loss_rate": 0.01
+    }"#;
+    let expected = LatencyModel::Network(NetworkModel {
+        rtt: RttSource::PerClient(Delay::Normal {
+            mean_ms: 60.0,
+            std_ms: 10.0,
+        }),
+        downlink_bandwidth: Some(125000.0),
+        loss_rate: 0.01,
+        ..NetworkModel::default()
+    });
+
+    assert_eq!(
+        toml::from_str::<LatencyModel>(toml_model).unwrap(),
+        expected
+    );
+    assert_eq!(
+        serde_json::from_str::<LatencyModel>(json_model).unwrap(),
+        expected
+    );
+    assert!(expected.network().is_ok());
+
+    // So can the example scenario
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/network.toml");
+    let scenario = Scenario::load(path).unwrap();
+    assert!(scenario.latency.network().is_ok());
+
+    // A negative standard deviation is an error rather than a panic, on either side
+    let bad_server = LatencyModel::Normal {
+        mean_ms: 30.0,
+        std_ms: -5.0,
+    };
+    assert!(bad_server.server_distr().is_err());
+    let bad_network = LatencyModel::Network(NetworkModel {
+        uplink_jitter: Delay::Normal {
+            mean_ms: 5.0,
+            std_ms: -1.0,
+        },
+        ..NetworkModel::default()
+    });
+    assert!(bad_network.network().is_err());
+}
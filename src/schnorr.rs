@@ -46,21 +46,33 @@
 * Verif(P, m, σ = (R', s')):
 *   c' := H(R', m)
 *   return s'G == R' + c'X
+*
+* BatchVerif(P, (m₁, σ₁), ..., (mₙ, σₙ)):
+*   z₁, ..., zₙ ← {0,1}¹²⁸
+*   cᵢ' := H(Rᵢ', mᵢ) for all i
+*   return (Σ zᵢsᵢ')G - Σ zᵢRᵢ' - (Σ zᵢcᵢ')X == 0
 */
 
-use crate::common::{FourMoveBlindSig, GroupElem, Scalar};
+use crate::{
+    common::{join_encoding, split_encoding, FourMoveBlindSig, GroupElem, Scalar},
+    error::Error,
+};
 
 use blake2::{digest::Digest, Blake2b};
-use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_TABLE, scalar::Scalar as ScalarRepr};
+use curve25519_dalek::{
+    constants::{RISTRETTO_BASEPOINT_POINT, RISTRETTO_BASEPOINT_TABLE},
+    ristretto::RistrettoPoint,
+    scalar::Scalar as ScalarRepr,
+    traits::{IsIdentity, VartimeMultiscalarMul},
+};
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
-pub struct Privkey(Scalar);
-#[derive(Clone, Copy)]
-pub struct Pubkey(GroupElem);
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Privkey(pub(crate) Scalar);
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Pubkey(pub(crate) GroupElem);
 
-#[derive(Clone, Copy)]
 pub struct ServerState {
     r: Scalar,
 }
@@ -91,12 +103,127 @@ pub struct ServerResp2 {
 // Used in protocol step 4
 #[derive(Clone, Copy, Deserialize, Serialize)]
 pub struct Signature {
-    R_prime: GroupElem,
-    s_prime: Scalar,
+    pub(crate) R_prime: GroupElem,
+    pub(crate) s_prime: Scalar,
+}
+
+// Canonical byte encodings. Scalars are 32 bytes little-endian, and group elements are 32-byte
+// compressed Ristretto points. Structs are the concatenation of their fields in order.
+
+impl Privkey {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Privkey, Error> {
+        Scalar::from_bytes(bytes).map(Privkey)
+    }
+}
+
+impl Pubkey {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Pubkey, Error> {
+        GroupElem::from_bytes(bytes).map(Pubkey)
+    }
+}
+
+impl ServerResp1 {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.R.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ServerResp1, Error> {
+        let R = GroupElem::from_bytes(bytes)?;
+        Ok(ServerResp1 { R })
+    }
+}
+
+impl ClientResp {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.c.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ClientResp, Error> {
+        let c = Scalar::from_bytes(bytes)?;
+        Ok(ClientResp { c })
+    }
+}
+
+impl ServerResp2 {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.s.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ServerResp2, Error> {
+        let s = Scalar::from_bytes(bytes)?;
+        Ok(ServerResp2 { s })
+    }
+}
+
+impl Signature {
+    /// Encodes the signature as R' || s'
+    pub fn to_bytes(&self) -> [u8; 64] {
+        join_encoding(&[self.R_prime.to_bytes(), self.s_prime.to_bytes()])
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, Error> {
+        let [R_prime, s_prime] = split_encoding::<2>(bytes, "signature")?;
+        Ok(Signature {
+            R_prime: GroupElem::from_bytes(&R_prime)?,
+            s_prime: Scalar::from_bytes(&s_prime)?,
+        })
+    }
 }
 
 pub struct BlindSchnorr;
 
+impl BlindSchnorr {
+    /// Verifies all the given signatures at once, by checking a random linear combination of
+    /// their verification equations. Returns true iff every signature is valid, except with
+    /// probability 2⁻¹²⁸.
+    pub fn verify_batch<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        pubkey: &Pubkey,
+        sigs: &[(&[u8], Signature)],
+    ) -> bool {
+        let Pubkey(X) = pubkey;
+
+        // zᵢ ← {0,1}¹²⁸. Short weights are enough for soundness.
+        let zs: Vec<ScalarRepr> = sigs
+            .iter()
+            .map(|_| {
+                let mut bytes = [0u8; 16];
+                rng.fill_bytes(&mut bytes);
+                ScalarRepr::from(u128::from_le_bytes(bytes))
+            })
+            .collect();
+
+        // Accumulate Σ zᵢsᵢ' and Σ zᵢcᵢ', where cᵢ' = H(Rᵢ', mᵢ)
+        let mut zs_sum = ScalarRepr::zero();
+        let mut zc_sum = ScalarRepr::zero();
+        for (z, (m, sig)) in zs.iter().zip(sigs.iter()) {
+            let c_prime =
+                ScalarRepr::from_hash(Blake2b::default().chain(sig.R_prime.to_bytes()).chain(m));
+            zs_sum += z * sig.s_prime.0;
+            zc_sum += z * c_prime;
+        }
+
+        // Check (Σ zᵢsᵢ')G - Σ zᵢRᵢ' - (Σ zᵢcᵢ')X == 0 in a single multiscalar multiplication.
+        // This is all public data, so variable time is fine.
+        let scalars = std::iter::once(zs_sum)
+            .chain(zs.iter().map(|z| -z))
+            .chain(std::iter::once(-zc_sum));
+        let points = std::iter::once(RISTRETTO_BASEPOINT_POINT)
+            .chain(sigs.iter().map(|(_, sig)| sig.R_prime.0))
+            .chain(std::iter::once(X.0));
+
+        RistrettoPoint::vartime_multiscalar_mul(scalars, points).is_identity()
+    }
+}
+
 impl FourMoveBlindSig for BlindSchnorr {
     // The Blind Schnorr is necessarily sequential
     const MAX_PARALLEL_SESSIONS: usize = 1;
@@ -182,7 +309,7 @@ impl FourMoveBlindSig for BlindSchnorr {
         (state, resp)
     }
 
-    fn sign2(privkey: &Privkey, state: &ServerState, client_resp: &ClientResp) -> ServerResp2 {
+    fn sign2(privkey: &Privkey, state: ServerState, client_resp: &ClientResp) -> ServerResp2 {
         let Privkey(x) = privkey;
         let ServerState { r } = state;
         let ClientResp { c } = client_resp;
@@ -196,7 +323,7 @@ impl FourMoveBlindSig for BlindSchnorr {
         state: &ClientState,
         _m: &[u8],
         server_resp2: &ServerResp2,
-    ) -> Option<Signature> {
+    ) -> Result<Signature, Error> {
         let Pubkey(X) = pubkey;
         let &ClientState { α, c, R, R_prime } = state;
         let ServerResp2 { s } = server_resp2;
@@ -204,12 +331,12 @@ impl FourMoveBlindSig for BlindSchnorr {
         // Check sG == R + cX
         let sG = &s.0 * &RISTRETTO_BASEPOINT_TABLE;
         if sG != R.0 + c.0 * X.0 {
-            return None;
+            return Err(Error::VerificationFailed);
         }
 
         let s_prime = Scalar(s.0 + α.0);
 
-        Some(Signature { R_prime, s_prime })
+        Ok(Signature { R_prime, s_prime })
     }
 }
 
@@ -222,8 +349,97 @@ fn test_correctness() {
     let (privkey, pubkey) = Alg::keygen(&mut csprng);
     let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
     let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
-    let server_resp2 = Alg::sign2(&privkey, &server_state, &client_resp);
+    let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
     let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
 
     assert!(Alg::verify(&pubkey, m, &sig));
 }
+
+#[test]
+fn test_batch_verify() {
+    let mut csprng = rand::thread_rng();
+    type Alg = BlindSchnorr;
+
+    let (privkey, pubkey) = Alg::keygen(&mut csprng);
+    let msgs: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i; 10]).collect();
+    let mut sigs: Vec<(&[u8], Signature)> = msgs
+        .iter()
+        .map(|m| {
+            let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
+            let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
+            let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
+            let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
+            (m.as_slice(), sig)
+        })
+        .collect();
+
+    assert!(Alg::verify_batch(&mut csprng, &pubkey, &[]));
+    assert!(Alg::verify_batch(&mut csprng, &pubkey, &sigs));
+
+    // Swap the messages of two signatures. Now the batch should fail.
+    let m0 = sigs[0].0;
+    sigs[0].0 = sigs[1].0;
+    sigs[1].0 = m0;
+    assert!(!Alg::verify_batch(&mut csprng, &pubkey, &sigs));
+}
+
+#[test]
+fn test_encoding_roundtrip() {
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    type Alg = BlindSchnorr;
+
+    let (privkey, pubkey) = Alg::keygen(&mut csprng);
+    let (server_state, server_resp1) = Alg::sign1(&mut csprng, &pubkey);
+    let (client_state, client_resp) = Alg::user1(&mut csprng, &pubkey, m, &server_resp1);
+    let server_resp2 = Alg::sign2(&privkey, server_state, &client_resp);
+    let sig = Alg::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
+
+    // Everything should decode to something that encodes to the same bytes
+    let privkey_bytes = privkey.to_bytes();
+    assert_eq!(
+        Privkey::from_bytes(&privkey_bytes).unwrap().to_bytes(),
+        privkey_bytes
+    );
+    let pubkey_bytes = pubkey.to_bytes();
+    assert_eq!(
+        Pubkey::from_bytes(&pubkey_bytes).unwrap().to_bytes(),
+        pubkey_bytes
+    );
+    let resp1_bytes = server_resp1.to_bytes();
+    assert_eq!(
+        ServerResp1::from_bytes(&resp1_bytes).unwrap().to_bytes(),
+        resp1_bytes
+    );
+    let client_resp_bytes = client_resp.to_bytes();
+    assert_eq!(
+        ClientResp::from_bytes(&client_resp_bytes)
+            .unwrap()
+            .to_bytes(),
+        client_resp_bytes
+    );
+    let resp2_bytes = server_resp2.to_bytes();
+    assert_eq!(
+        ServerResp2::from_bytes(&resp2_bytes).unwrap().to_bytes(),
+        resp2_bytes
+    );
+
+    // A decoded signature should still verify
+    let sig_bytes = sig.to_bytes();
+    let decoded_sig = Signature::from_bytes(&sig_bytes).unwrap();
+    assert_eq!(decoded_sig.to_bytes(), sig_bytes);
+    assert!(Alg::verify(
+        &Pubkey::from_bytes(&pubkey_bytes).unwrap(),
+        m,
+        &decoded_sig
+    ));
+
+    // Wrong lengths, non-canonical scalars, and invalid points should all be rejected
+    assert!(Signature::from_bytes(&sig_bytes[..63]).is_err());
+    let mut bad_sig = sig_bytes;
+    bad_sig[32..].copy_from_slice(&[0xff; 32]);
+    assert!(Signature::from_bytes(&bad_sig).is_err());
+    let mut bad_sig = sig_bytes;
+    bad_sig[..32].copy_from_slice(&[0xff; 32]);
+    assert!(Signature::from_bytes(&bad_sig).is_err());
+}
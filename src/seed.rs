@@ -0,0 +1,134 @@
+/*
+Seeding the workload. With a seed, every random choice that shapes a workload comes out the same on
+every run: when clients arrive, each client's RTT and per-message delays, and the server's latency
+sleeps. That way a code change can be measured against exactly the same workload it was measured
+on before.
+
+Every kind of choice gets its own stream, seeded from the seed, the stream, and a label naming the
+benchmark. Separate streams mean that, e.g., a network model that draws more numbers doesn't shift
+the arrival schedule. The label is the bench name, which doesn't include the scheme, so every scheme
+sees the same workload. bench_all.rs starts every stream over at the start of each iteration, so
+every iteration replays the same workload too, however many of them Criterion's warm-up took.
+
+The server's sleeps come out in the same sequence, but with more than one server thread, which
+request gets which sleep depends on how the threads are scheduled. That part isn't reproducible.
+
+None of this touches cryptographic randomness. Keys, nonces and blinding factors still come from
+thread_rng, seed or no seed.
+*/
+
+use std::sync::{Arc, Mutex};
+
+use rand::{distributions::Distribution, rngs::StdRng, Rng, SeedableRng};
+
+/// The environment variable that sets the seed, overriding the scenario's
+pub const SEED_VAR: &str = "BENCH_SEED";
+
+/// The kinds of random choices that make up a workload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    /// When clients arrive
+    Arrivals,
+    /// Each client's connection to the network model
+    Network,
+    /// How long the server sleeps after each response
+    ServerLatency,
+    /// How long clients back off before retrying. Only the simulator uses this. Real clients back
+    /// off with thread_rng, since whether they retry at all depends on timing.
+    Backoff,
+}
+
+/// Returns the RNG for the given stream of the labeled benchmark. With a seed, it's the same every
+/// time. Without one, it's seeded from thread_rng.
+pub fn stream_rng(seed: Option<u64>, label: &str, stream: Stream) -> StdRng {
+    let seed = match seed {
+        Some(seed) => seed,
+        None => return StdRng::seed_from_u64(rand::thread_rng().gen()),
+    };
+
+    // Mix everything together with 64-bit FNV-1a. The standard library's hashers aren't guaranteed
+    // to stay the same between Rust versions, and this has to.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let stream_byte = stream as u8;
+    for &b in seed
+        .to_le_bytes()
+        .iter()
+        .chain(label.as_bytes())
+        .chain(&[stream_byte])
+    {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    StdRng::seed_from_u64(hash)
+}
+
+/// A distribution that samples with its own RNG, ignoring the one it's given. Wrapping the server's
+/// latency distribution in this makes its sleeps a fixed sequence: the nth response the server
+/// sends sleeps for the nth sample. Clones share the RNG, so a clone kept outside the server can
+/// start the sequence over.
+#[derive(Clone)]
+pub struct SeededDistr<D> {
+    distr: D,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl<D> SeededDistr<D> {
+    pub fn new(distr: D, rng: StdRng) -> SeededDistr<D> {
+        SeededDistr {
+            distr,
+            rng: Arc::new(Mutex::new(rng)),
+        }
+    }
+
+    /// Replaces the RNG, here and in every clone
+    pub fn reseed(&self, rng: StdRng) {
+        *self.rng.lock().unwrap() = rng;
+    }
+}
+
+impl<T, D: Distribution<T>> Distribution<T> for SeededDistr<D> {
+    fn sample<R: Rng + ?Sized>(&self, _rng: &mut R) -> T {
+        self.distr.sample(&mut *self.rng.lock().unwrap())
+    }
+}
+
+// The same seed, label and stream always give the same numbers, and changing any of them gives
+// different ones
+#[test]
+fn test_stream_rng() {
+    let draw = |seed, label, stream| -> Vec<u64> {
+        let mut rng = stream_rng(seed, label, stream);
+        (0..4).map(|_| rng.gen()).collect()
+    };
+
+    let label = "4-core server handling 100 clients at 10ms EIAT";
+    let expected = draw(Some(7), label, Stream::Arrivals);
+    assert_eq!(draw(Some(7), label, Stream::Arrivals), expected);
+    assert_ne!(draw(Some(8), label, Stream::Arrivals), expected);
+    assert_ne!(draw(Some(7), "loadgen", Stream::Arrivals), expected);
+    assert_ne!(draw(Some(7), label, Stream::Network), expected);
+    assert_ne!(
+        draw(None, label, Stream::Arrivals),
+        draw(None, label, Stream::Arrivals)
+    );
+
+    // A seeded distribution doesn't care what RNG it's handed
+    let distr = rand_distr::Normal::new(30.0, 5.0).unwrap();
+    let seeded = |rng| SeededDistr::new(distr, rng);
+    let (a, b) = (
+        seeded(stream_rng(Some(7), label, Stream::ServerLatency)),
+        seeded(stream_rng(Some(7), label, Stream::ServerLatency)),
+    );
+    let mut thread_rng = rand::thread_rng();
+    for _ in 0..4 {
+        assert_eq!(a.sample(&mut thread_rng), b.sample(&mut thread_rng));
+    }
+
+    // Reseeding a clone starts the original's sequence over
+    let first: f64 = a.sample(&mut thread_rng);
+    a.clone()
+        .reseed(stream_rng(Some(7), label, Stream::ServerLatency));
+    let replayed: Vec<f64> = (0..5).map(|_| a.sample(&mut thread_rng)).collect();
+    assert_eq!(replayed[4], first);
+}
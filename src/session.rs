@@ -0,0 +1,372 @@
+use crate::error::Error;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc, Condvar, Mutex,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use rand::Rng;
+
+/// What the server does with a new session when every slot is taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdmissionPolicy {
+    /// Turn the client away with `Error::CapacityExceeded`. It's up to the client to retry.
+    Reject,
+    /// Wait in line for a slot, first come first served. A request that waits for longer than the
+    /// session timeout gets turned away. Every waiting request ties up a server thread, so the
+    /// line is only as long as the store's waiting limit, and anyone who'd be further back gets
+    /// turned away as if the policy were `Reject`. The server sets the limit so that a thread is
+    /// always left over for the sessions holding the slots.
+    Queue,
+}
+
+/// A session, along with the last time its client was heard from
+struct Entry<T> {
+    session: T,
+    last_active: Instant,
+}
+
+/// Bookkeeping for session slots
+#[derive(Default)]
+struct Slots {
+    /// Number of sessions that currently hold a slot
+    in_use: usize,
+    /// Tickets of the requests waiting for a slot, in the order they arrived
+    queue: VecDeque<u64>,
+    next_ticket: u64,
+}
+
+/// The server's state for every open session, keyed by session token. Tokens are issued by the
+/// store, are unguessable, and are good for exactly one `take`.
+///
+/// Every open session holds one of a fixed number of slots, from when it's admitted until it
+/// finishes, fails, or is evicted. Admission is atomic, so there are never more sessions than
+/// slots.
+///
+/// A session that goes untouched for longer than the timeout is considered abandoned. It can't be
+/// resumed, and it gets evicted the next time the store is reaped.
+pub struct SessionStore<T> {
+    sessions: DashMap<String, Entry<T>>,
+    timeout: Duration,
+    policy: AdmissionPolicy,
+    slots: Mutex<Slots>,
+    slot_freed: Condvar,
+    /// How many requests can wait in line at once
+    max_waiting: AtomicUsize,
+    num_evicted: AtomicUsize,
+    num_rejected: AtomicUsize,
+}
+
+impl<T> SessionStore<T> {
+    pub fn new(timeout: Duration, policy: AdmissionPolicy) -> SessionStore<T> {
+        SessionStore {
+            sessions: DashMap::new(),
+            timeout,
+            policy,
+            slots: Mutex::new(Slots::default()),
+            slot_freed: Condvar::new(),
+            max_waiting: AtomicUsize::new(usize::MAX),
+            num_evicted: AtomicUsize::new(0),
+            num_rejected: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns the number of stored sessions. This includes abandoned sessions that haven't been
+    /// reaped yet, and leaves out sessions whose next message is being processed.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.sessions.contains_key(token)
+    }
+
+    /// Returns the number of sessions holding a slot. Unlike `len`, this counts sessions whose
+    /// next message is being processed.
+    pub fn num_admitted(&self) -> usize {
+        self.slots.lock().unwrap().in_use
+    }
+
+    /// Caps how many requests can wait in line for a slot under `AdmissionPolicy::Queue`. There's
+    /// no cap until this is called.
+    pub fn limit_waiting(&self, max_waiting: usize) {
+        self.max_waiting.store(max_waiting, SeqCst);
+    }
+
+    /// Reserves a slot for a new session, as long as fewer than `capacity` are in use. If they're
+    /// all in use, what happens depends on the admission policy.
+    pub fn admit(&self, capacity: usize) -> Result<(), Error> {
+        let mut slots = self.slots.lock().unwrap();
+
+        if self.policy == AdmissionPolicy::Reject || capacity == 0 {
+            if slots.in_use < capacity {
+                slots.in_use += 1;
+                return Ok(());
+            } else {
+                self.num_rejected.fetch_add(1, SeqCst);
+                return Err(Error::CapacityExceeded);
+            }
+        }
+
+        // Don't get in line if there's no need to, or if it's already as long as it can be
+        if slots.queue.is_empty() && slots.in_use < capacity {
+            slots.in_use += 1;
+            return Ok(());
+        }
+        if slots.queue.len() >= self.max_waiting.load(SeqCst) {
+            self.num_rejected.fetch_add(1, SeqCst);
+            return Err(Error::CapacityExceeded);
+        }
+
+        // Get in line, and wait until we're at the front and there's a free slot
+        let ticket = slots.next_ticket;
+        slots.next_ticket += 1;
+        slots.queue.push_back(ticket);
+        let deadline = Instant::now() + self.timeout;
+
+        while !(slots.queue.front() == Some(&ticket) && slots.in_use < capacity) {
+            let now = Instant::now();
+            if now >= deadline {
+                // Give up our place in line. Whoever's behind us might be next.
+                slots.queue.retain(|&t| t != ticket);
+                self.slot_freed.notify_all();
+                self.num_rejected.fetch_add(1, SeqCst);
+                return Err(Error::CapacityExceeded);
+            }
+            slots = self
+                .slot_freed
+                .wait_timeout(slots, deadline - now)
+                .unwrap()
+                .0;
+        }
+
+        slots.queue.pop_front();
+        slots.in_use += 1;
+        // The next one in line might be able to go too
+        self.slot_freed.notify_all();
+        Ok(())
+    }
+
+    /// Frees up `n` slots
+    fn release_slots(&self, n: usize) {
+        if n > 0 {
+            let mut slots = self.slots.lock().unwrap();
+            slots.in_use = slots.in_use.saturating_sub(n);
+            self.slot_freed.notify_all();
+        }
+    }
+
+    /// Frees up the slot of a session that's over. Every successful `admit` must be matched by
+    /// either this or an `insert` of the session.
+    pub fn release(&self) {
+        self.release_slots(1);
+    }
+
+    /// Removes and returns the session with the given token. The session keeps its slot until it's
+    /// either stored again with `insert` or ended with `release`. Returns `None` if the token was
+    /// never issued or was already used, or if the session was abandoned.
+    pub fn take(&self, token: &str) -> Option<T> {
+        let (_, entry) = self.sessions.remove(token)?;
+        if entry.last_active.elapsed() > self.timeout {
+            self.num_evicted.fetch_add(1, SeqCst);
+            self.release();
+            None
+        } else {
+            Some(entry.session)
+        }
+    }
+
+    /// Stores the given session under a fresh token, and marks it as active as of now. Returns the
+    /// token. The session must hold a slot.
+    pub fn insert(&self, session: T) -> String {
+        // 128 random bits, hex-encoded. This is both unguessable and collision-free.
+        let token_bytes: [u8; 16] = rand::thread_rng().gen();
+        let token: String = token_bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let entry = Entry {
+            session,
+            last_active: Instant::now(),
+        };
+        self.sessions.insert(token.clone(), entry);
+        token
+    }
+
+    /// Evicts every abandoned session and frees up its slot. Returns how many were evicted.
+    pub fn reap(&self) -> usize {
+        let mut num_evicted = 0;
+        self.sessions.retain(|_, entry| {
+            let abandoned = entry.last_active.elapsed() > self.timeout;
+            num_evicted += abandoned as usize;
+            !abandoned
+        });
+
+        self.release_slots(num_evicted);
+        self.num_evicted.fetch_add(num_evicted, SeqCst);
+        num_evicted
+    }
+
+    /// Returns the total number of sessions evicted over the lifetime of the store
+    pub fn num_evicted(&self) -> usize {
+        self.num_evicted.load(SeqCst)
+    }
+
+    /// Returns the total number of new sessions turned away over the lifetime of the store
+    pub fn num_rejected(&self) -> usize {
+        self.num_rejected.load(SeqCst)
+    }
+}
+
+/// Starts a thread that reaps the given store every `interval`, or every millisecond if that's
+/// shorter, so a tiny timeout doesn't have it spinning. The thread only holds a weak reference,
+/// and exits once the store is dropped.
+pub fn start_reaper<T>(store: &Arc<SessionStore<T>>, interval: Duration)
+where
+    T: Send + Sync + 'static,
+{
+    let interval = interval.max(Duration::from_millis(1));
+    let store = Arc::downgrade(store);
+    std::thread::spawn(move || loop {
+        sleep(interval);
+        match store.upgrade() {
+            Some(store) => {
+                store.reap();
+            }
+            None => break,
+        }
+    });
+}
+
+#[test]
+fn test_reap() {
+    let store = SessionStore::new(Duration::from_millis(50), AdmissionPolicy::Reject);
+    store.admit(2).unwrap();
+    let a = store.insert(());
+    sleep(Duration::from_millis(100));
+
+    // b is fresh, so a is the only one that gets reaped. That frees up a's slot.
+    store.admit(2).unwrap();
+    let b = store.insert(());
+    assert_eq!(store.admit(2), Err(Error::CapacityExceeded));
+    assert_eq!(store.reap(), 1);
+    assert_eq!(store.len(), 1);
+    assert!(!store.contains(&a));
+    assert!(store.contains(&b));
+    store.admit(2).unwrap();
+
+    // An abandoned session can't be resumed, even if it hasn't been reaped yet
+    sleep(Duration::from_millis(100));
+    assert!(store.take(&b).is_none());
+    assert_eq!(store.num_evicted(), 2);
+}
+
+// Tokens are unique, and each one can only be used once
+#[test]
+fn test_tokens() {
+    let store = SessionStore::new(Duration::from_secs(10), AdmissionPolicy::Reject);
+    let a = store.insert(1);
+    let b = store.insert(2);
+    assert_ne!(a, b);
+    assert_eq!(a.len(), 32);
+
+    assert_eq!(store.take(&a), Some(1));
+    assert_eq!(store.take(&a), None);
+    assert_eq!(store.take("not a token"), None);
+    assert_eq!(store.take(&b), Some(2));
+}
+
+// However many threads race for the slots, only as many as there are slots get in
+#[test]
+fn test_admission_is_atomic() {
+    let store = Arc::new(SessionStore::<()>::new(
+        Duration::from_secs(10),
+        AdmissionPolicy::Reject,
+    ));
+    let threads: Vec<_> = (0..32)
+        .map(|_| {
+            let store = store.clone();
+            std::thread::spawn(move || store.admit(3).is_ok())
+        })
+        .collect();
+    let num_admitted = threads
+        .into_iter()
+        .map(|t| t.join().unwrap())
+        .filter(|&admitted| admitted)
+        .count();
+
+    assert_eq!(num_admitted, 3);
+    assert_eq!(store.num_rejected(), 29);
+}
+
+// Queued requests get slots in the order they arrived
+#[test]
+fn test_admission_queue() {
+    use std::sync::mpsc;
+
+    let store = Arc::new(SessionStore::<()>::new(
+        Duration::from_secs(10),
+        AdmissionPolicy::Queue,
+    ));
+    store.admit(1).unwrap();
+
+    // Line up three requests behind the one holding the slot
+    let (admitted_send, admitted) = mpsc::channel();
+    for i in 0..3 {
+        let store = store.clone();
+        let admitted_send = admitted_send.clone();
+        std::thread::spawn(move || {
+            store.admit(1).unwrap();
+            admitted_send.send(i).unwrap();
+        });
+        sleep(Duration::from_millis(50));
+    }
+
+    // Nobody gets in until the slot's freed, and then they get in one at a time
+    assert!(admitted.try_recv().is_err());
+    for i in 0..3 {
+        store.release();
+        assert_eq!(admitted.recv().unwrap(), i);
+        sleep(Duration::from_millis(50));
+        assert!(admitted.try_recv().is_err());
+    }
+    assert_eq!(store.num_rejected(), 0);
+}
+
+// Once the line's as long as it's allowed to be, newcomers get turned away instead of waiting
+#[test]
+fn test_admission_queue_limit() {
+    let store = Arc::new(SessionStore::<()>::new(
+        Duration::from_secs(10),
+        AdmissionPolicy::Queue,
+    ));
+    store.limit_waiting(1);
+    store.admit(1).unwrap();
+
+    let waiter = {
+        let store = store.clone();
+        std::thread::spawn(move || store.admit(1))
+    };
+    sleep(Duration::from_millis(50));
+    assert_eq!(store.admit(1), Err(Error::CapacityExceeded));
+    assert_eq!(store.num_rejected(), 1);
+
+    store.release();
+    waiter.join().unwrap().unwrap();
+    assert_eq!(store.num_admitted(), 1);
+
+    // With no room in line at all, it's the same as rejecting
+    store.limit_waiting(0);
+    assert_eq!(store.admit(1), Err(Error::CapacityExceeded));
+}
@@ -0,0 +1,650 @@
+/*
+A discrete-event simulation of bench_all.rs. Instead of running crypto and sleeping for real, it
+charges every step of the protocol what the per-step benchmark measured, and moves a virtual clock
+from one event to the next. A data point that takes minutes to benchmark takes milliseconds to
+simulate, and host noise doesn't come into it.
+
+What's simulated is the same as what bench_all.rs runs: clients arrive according to the scenario's
+arrival process, and each runs one four-move session. The server has a fixed number of threads,
+and requests wait in line for a free one. A new session is turned away if the scheme's
+MAX_PARALLEL_SESSIONS are already open, and its client backs off and retries according to the
+default RetryPolicy. Every response ties up its server thread for the scenario's server-side latency
+sleep, and every message is delayed by the scenario's network model, if it has one. On the async
+backend, the sleep doesn't tie up a thread: the thread is free as soon as the crypto is done.
+
+What isn't simulated is everything the per-step benchmark doesn't measure: serialization, HTTP,
+thread wakeups, and the clients' share of the CPU. Message sizes are taken to be 0, so a network
+model's bandwidth limits don't apply.
+
+The cost of a server step depends on how many sessions are computing at once. If j are, a step
+costs what the per-step benchmark measured for s = j sessions, interpolated between the values of s
+it ran with, and growing proportionally past the largest.
+*/
+
+use crate::{
+    abe::Abe,
+    blind_bls::BlindBls,
+    blind_rsa::BlindRsaRandomized,
+    clause_schnorr::ClauseBlindSchnorr,
+    common::BlindSigProtocol,
+    latency::{DelayedTransport, Direction, Network},
+    results::{discover_steps, BenchId, Estimates},
+    retry::RetryPolicy,
+    scenario::{ArrivalProcess, Scenario, Scheme, ServerBackend},
+    schnorr::BlindSchnorr,
+    seed::{stream_rng, Stream},
+};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+    error::Error,
+    path::Path,
+    time::Duration,
+};
+
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Normal};
+
+/// Returns how many sessions the scheme lets the server have open at once
+pub fn max_parallel_sessions(scheme: Scheme) -> usize {
+    match scheme {
+        Scheme::BlindSchnorr => <BlindSchnorr as BlindSigProtocol>::MAX_PARALLEL_SESSIONS,
+        Scheme::ClauseBlindSchnorr => {
+            <ClauseBlindSchnorr as BlindSigProtocol>::MAX_PARALLEL_SESSIONS
+        }
+        Scheme::Abe => <Abe as BlindSigProtocol>::MAX_PARALLEL_SESSIONS,
+        Scheme::BlindRsa => <BlindRsaRandomized as BlindSigProtocol>::MAX_PARALLEL_SESSIONS,
+        Scheme::BlindBls => <BlindBls as BlindSigProtocol>::MAX_PARALLEL_SESSIONS,
+    }
+}
+
+/// How long each of the 4 steps of the protocol takes, from the per-step benchmark
+#[derive(Clone, Debug)]
+pub struct StepCosts {
+    /// For every step, the numbers of sessions it was run with at once, and how long they took in
+    /// seconds. These are sorted by number of sessions.
+    steps: Vec<Vec<(usize, f64)>>,
+}
+
+impl StepCosts {
+    /// Reads the per-step results for the given benchmark group out of a Criterion output
+    /// directory. Fails if any step is missing.
+    pub fn load<P: AsRef<Path>>(dir: P, group: &str) -> Result<StepCosts, Box<dyn Error>> {
+        let mut steps = vec![Vec::new(); 4];
+        // These come sorted by number of sessions
+        for result in discover_steps(dir)? {
+            if result.id.group == group && (1..=4).contains(&result.id.step) {
+                let seconds = result.estimates()?.mean / 1e9;
+                steps[result.id.step - 1].push((result.id.num_sessions, seconds));
+            }
+        }
+
+        match steps.iter().position(Vec::is_empty) {
+            Some(i) => Err(format!("no per-step results for step {} of {}", i + 1, group).into()),
+            None => Ok(StepCosts { steps }),
+        }
+    }
+
+    /// Every step takes the given number of seconds, no matter how many sessions run it at once
+    pub fn constant(seconds: [f64; 4]) -> StepCosts {
+        StepCosts {
+            steps: seconds.iter().map(|&t| vec![(1, t)]).collect(),
+        }
+    }
+
+    /// How long the given step, from 1 to 4, takes when `concurrent` sessions are running it at
+    /// once, in seconds
+    pub fn cost(&self, step: usize, concurrent: usize) -> f64 {
+        let costs = &self.steps[step - 1];
+        let n = concurrent.max(1) as f64;
+
+        let (s, t) = costs[0];
+        if n <= s as f64 {
+            return t;
+        }
+        for pair in costs.windows(2) {
+            let ((s1, t1), (s2, t2)) = (pair[0], pair[1]);
+            if n <= s2 as f64 {
+                return t1 + (t2 - t1) * (n - s1 as f64) / (s2 - s1) as f64;
+            }
+        }
+        let (s, t) = costs[costs.len() - 1];
+        t * n / s as f64
+    }
+}
+
+/// One benchmark to simulate
+pub struct SimParams<'a> {
+    pub costs: &'a StepCosts,
+    /// Number of server threads
+    pub pool_size: usize,
+    pub max_parallel_sessions: usize,
+    pub num_clients: usize,
+    pub arrival_process: ArrivalProcess,
+    pub mean_interarrival: Duration,
+    /// How long the server sleeps after each response, in milliseconds
+    pub server_latency: Normal<f64>,
+    pub network: &'a Network,
+    pub retry_policy: RetryPolicy,
+    pub server_backend: ServerBackend,
+}
+
+/// Where a simulation's randomness comes from. These are the same streams bench_all.rs uses, so the
+/// first run of a seeded simulation sees the same workload as every iteration of a benchmark run
+/// with the same seed. Later runs carry on with the streams, so that the runtimes have a spread.
+pub struct SimRngs {
+    pub arrivals: StdRng,
+    pub network: StdRng,
+    pub backoff: StdRng,
+    /// The server's latency sleeps
+    pub server: StdRng,
+}
+
+impl SimRngs {
+    pub fn new(seed: Option<u64>, label: &str) -> SimRngs {
+        SimRngs {
+            arrivals: stream_rng(seed, label, Stream::Arrivals),
+            network: stream_rng(seed, label, Stream::Network),
+            backoff: stream_rng(seed, label, Stream::Backoff),
+            server: stream_rng(seed, label, Stream::ServerLatency),
+        }
+    }
+}
+
+/// What happened in one simulated run of a benchmark
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RunOutcome {
+    /// How long the run took, in seconds. Like in bench_all.rs, this is from the first client
+    /// arriving until every client is done, and the last client's interarrival time, whichever is
+    /// later.
+    pub runtime: f64,
+    pub num_retries: u64,
+    /// Clients that gave up. In bench_all.rs, this would fail the benchmark.
+    pub num_failed: usize,
+}
+
+/// The two messages a client sends
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Msg {
+    Start,
+    ClientResp,
+}
+
+/// What a client gets back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Reply {
+    Rejected,
+    ServerResp1,
+    ServerResp2,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Event {
+    /// A client sends its current message
+    Send(usize),
+    /// A client's message gets to the server
+    Request(usize),
+    /// A server thread is done computing a client's response
+    Computed(usize),
+    /// A server thread is done with its latency sleep, and sends the response
+    Responded(usize),
+    /// A response gets to its client
+    Response(usize, Reply),
+}
+
+/// An event at a point in virtual time. Events at the same time happen in the order they were
+/// scheduled.
+struct Scheduled {
+    time: f64,
+    seq: u64,
+    event: Event,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Scheduled) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Scheduled) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    // BinaryHeap is a max-heap, so the earliest event has to compare as the greatest
+    fn cmp(&self, other: &Scheduled) -> Ordering {
+        other
+            .time
+            .total_cmp(&self.time)
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+struct Client {
+    link: DelayedTransport<()>,
+    /// The message the client is trying to get through
+    msg: Msg,
+    /// The RTT of the current round trip
+    rtt_ms: f64,
+    /// How many times the current message has been sent
+    attempt: u32,
+    arrival: f64,
+}
+
+/// The state of one simulated run
+struct Sim<'a, 'b> {
+    params: &'a SimParams<'a>,
+    server_rng: &'b mut StdRng,
+    backoff_rng: &'b mut StdRng,
+    events: BinaryHeap<Scheduled>,
+    next_seq: u64,
+    clients: Vec<Client>,
+    free_threads: usize,
+    /// Requests waiting for a server thread
+    waiting: VecDeque<usize>,
+    open_sessions: usize,
+    /// Server threads that are computing rather than waiting or sleeping
+    computing: usize,
+    outcome: RunOutcome,
+}
+
+impl<'a, 'b> Sim<'a, 'b> {
+    fn schedule(&mut self, time: f64, event: Event) {
+        self.events.push(Scheduled {
+            time,
+            seq: self.next_seq,
+            event,
+        });
+        self.next_seq += 1;
+    }
+
+    fn delay(&self, client: usize, direction: Direction) -> f64 {
+        let c = &self.clients[client];
+        c.link.next_delay(direction, c.rtt_ms, 0).as_secs_f64()
+    }
+
+    /// Starts handling a client's request on a free server thread. Returns whether the thread is
+    /// still busy afterwards, which it isn't if the request was turned away.
+    fn serve(&mut self, client: usize, now: f64) -> bool {
+        let step = match self.clients[client].msg {
+            Msg::Start if self.open_sessions >= self.params.max_parallel_sessions => {
+                // Turned away right away, without a latency sleep
+                let down = self.delay(client, Direction::Downlink);
+                self.schedule(now + down, Event::Response(client, Reply::Rejected));
+                return false;
+            }
+            Msg::Start => {
+                self.open_sessions += 1;
+                1
+            }
+            Msg::ClientResp => 3,
+        };
+
+        self.computing += 1;
+        let cost = self.params.costs.cost(step, self.computing);
+        self.schedule(now + cost, Event::Computed(client));
+        true
+    }
+
+    /// Hands a server thread that just finished to the next request in line, if there is one
+    fn free_thread(&mut self, now: f64) {
+        while let Some(next) = self.waiting.pop_front() {
+            if self.serve(next, now) {
+                return;
+            }
+        }
+        self.free_threads += 1;
+    }
+
+    fn handle(&mut self, now: f64, event: Event) {
+        let costs = self.params.costs;
+        match event {
+            Event::Send(c) => {
+                let client = &mut self.clients[c];
+                client.rtt_ms = client.link.next_rtt();
+                let up = self.delay(c, Direction::Uplink);
+                self.schedule(now + up, Event::Request(c));
+            }
+            Event::Request(c) => {
+                if self.free_threads > 0 {
+                    self.free_threads -= 1;
+                    if !self.serve(c, now) {
+                        self.free_thread(now);
+                    }
+                } else {
+                    self.waiting.push_back(c);
+                }
+            }
+            Event::Computed(c) => {
+                self.computing -= 1;
+                // The session gives up its slot as soon as it's done, before the sleep
+                if self.clients[c].msg == Msg::ClientResp {
+                    self.open_sessions -= 1;
+                }
+                // The async server sleeps on a timer, so the thread can move on
+                if self.params.server_backend == ServerBackend::Async {
+                    self.free_thread(now);
+                }
+                // The server sleeps for a whole number of milliseconds
+                let pause_ms = self.params.server_latency.sample(self.server_rng).max(0.0);
+                self.schedule(now + pause_ms.trunc() / 1000.0, Event::Responded(c));
+            }
+            Event::Responded(c) => {
+                if self.params.server_backend == ServerBackend::ThreadPool {
+                    self.free_thread(now);
+                }
+                let reply = match self.clients[c].msg {
+                    Msg::Start => Reply::ServerResp1,
+                    Msg::ClientResp => Reply::ServerResp2,
+                };
+                let down = self.delay(c, Direction::Downlink);
+                self.schedule(now + down, Event::Response(c, reply));
+            }
+            Event::Response(c, Reply::Rejected) => {
+                let policy = &self.params.retry_policy;
+                let client = &mut self.clients[c];
+                let backoff = policy
+                    .backoff(self.backoff_rng, client.attempt - 1)
+                    .as_secs_f64();
+                let out_of_time = policy
+                    .deadline
+                    .is_some_and(|d| now + backoff > client.arrival + d.as_secs_f64());
+
+                if client.attempt >= policy.max_attempts || out_of_time {
+                    self.outcome.num_failed += 1;
+                    self.finish(now);
+                } else {
+                    client.attempt += 1;
+                    self.outcome.num_retries += 1;
+                    self.schedule(now + backoff, Event::Send(c));
+                }
+            }
+            Event::Response(c, Reply::ServerResp1) => {
+                let client = &mut self.clients[c];
+                client.msg = Msg::ClientResp;
+                client.attempt = 1;
+                self.schedule(now + costs.cost(2, 1), Event::Send(c));
+            }
+            Event::Response(_, Reply::ServerResp2) => self.finish(now + costs.cost(4, 1)),
+        }
+    }
+
+    fn finish(&mut self, time: f64) {
+        self.outcome.runtime = self.outcome.runtime.max(time);
+    }
+}
+
+/// Simulates one run of a benchmark
+pub fn simulate_run(params: &SimParams, rngs: &mut SimRngs) -> RunOutcome {
+    let mut sim = Sim {
+        params,
+        server_rng: &mut rngs.server,
+        backoff_rng: &mut rngs.backoff,
+        events: BinaryHeap::new(),
+        next_seq: 0,
+        clients: Vec::with_capacity(params.num_clients),
+        free_threads: params.pool_size,
+        waiting: VecDeque::new(),
+        open_sessions: 0,
+        computing: 0,
+        outcome: RunOutcome::default(),
+    };
+
+    // Every client arrives, then the next one is an interarrival time behind it. The run lasts at
+    // least until the last of those interarrival times is up.
+    let mut arrival = 0.0;
+    for c in 0..params.num_clients {
+        sim.clients.push(Client {
+            link: params.network.connect(&mut rngs.network, ()),
+            msg: Msg::Start,
+            rtt_ms: 0.0,
+            attempt: 1,
+            arrival,
+        });
+        sim.schedule(arrival, Event::Send(c));
+        arrival += params
+            .arrival_process
+            .sample(&mut rngs.arrivals, params.mean_interarrival)
+            .as_secs_f64();
+    }
+    sim.finish(arrival);
+
+    while let Some(Scheduled { time, event, .. }) = sim.events.pop() {
+        sim.handle(time, event);
+    }
+
+    sim.outcome
+}
+
+/// What came out of simulating a benchmark many times
+#[derive(Clone, Debug)]
+pub struct SimResult {
+    pub id: BenchId,
+    /// The name bench_all.rs gives the benchmark
+    pub bench_name: String,
+    /// The runtime, in nanoseconds like Criterion's. The confidence interval is a normal
+    /// approximation.
+    pub estimates: Estimates,
+    pub num_retries: u64,
+    pub num_failed: usize,
+}
+
+/// Summarizes runtimes in seconds as Criterion-style estimates in nanoseconds
+fn estimates_of(runtimes: &[f64]) -> Estimates {
+    let n = runtimes.len() as f64;
+    let mean = runtimes.iter().sum::<f64>() / n;
+    let var = runtimes.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+    let std_dev = var.sqrt();
+    // 1.96 standard errors either side is a 95% confidence interval. With few, spread out runs,
+    // that can reach down past 0, so it's cut off at the fastest run, which is never more than
+    // the mean and always more than 0.
+    let half_width = 1.96 * std_dev / n.sqrt();
+    let fastest = runtimes.iter().copied().fold(f64::INFINITY, f64::min);
+
+    Estimates {
+        mean: mean * 1e9,
+        mean_lower: (mean - half_width).max(fastest) * 1e9,
+        mean_upper: (mean + half_width) * 1e9,
+        confidence_level: 0.95,
+        std_dev: std_dev * 1e9,
+    }
+}
+
+/// Simulates every benchmark in the scenario, each for the given number of iterations. The step
+/// costs come from the per-step results in `piecewise_dir`. Schemes without any are skipped, and
+/// returned along with the results.
+pub fn simulate_scenario<P: AsRef<Path>>(
+    scenario: &Scenario,
+    piecewise_dir: P,
+    iterations: usize,
+) -> Result<(Vec<SimResult>, Vec<Scheme>), Box<dyn Error>> {
+    let network = scenario.latency.network()?;
+    let server_latency = scenario.latency.server_distr()?;
+    let mut results = Vec::new();
+    let mut skipped = Vec::new();
+
+    for run in &scenario.schemes {
+        // The per-step benchmark doesn't care about the backend
+        let costs = match StepCosts::load(piecewise_dir.as_ref(), run.scheme.group_name()) {
+            Ok(costs) => costs,
+            Err(_) => {
+                skipped.push(run.scheme);
+                continue;
+            }
+        };
+
+        for &pool_size in &run.pool_sizes {
+            for &eiat_ms in &scenario.interarrival_times_ms {
+                let bench_name = scenario.bench_name(pool_size, eiat_ms);
+                let params = SimParams {
+                    costs: &costs,
+                    pool_size,
+                    max_parallel_sessions: max_parallel_sessions(run.scheme),
+                    num_clients: scenario.num_clients,
+                    arrival_process: scenario.arrival_process,
+                    mean_interarrival: Duration::from_secs_f64(eiat_ms / 1000.0),
+                    server_latency,
+                    network: &network,
+                    retry_policy: RetryPolicy::default(),
+                    server_backend: scenario.server_backend,
+                };
+                let mut rngs = SimRngs::new(scenario.seed, &bench_name);
+
+                let mut runtimes = Vec::with_capacity(iterations);
+                let (mut num_retries, mut num_failed) = (0, 0);
+                for _ in 0..iterations {
+                    let outcome = simulate_run(&params, &mut rngs);
+                    runtimes.push(outcome.runtime);
+                    num_retries += outcome.num_retries;
+                    num_failed += outcome.num_failed;
+                }
+
+                results.push(SimResult {
+                    id: BenchId {
+                        group: scenario.group_name(run.scheme),
+                        pool_size,
+                        num_clients: scenario.num_clients,
+                        eiat_ms,
+                    },
+                    bench_name,
+                    estimates: estimates_of(&runtimes),
+                    num_retries,
+                    num_failed,
+                });
+            }
+        }
+    }
+
+    Ok((results, skipped))
+}
+
+/// Writes the results out the way Criterion lays them out, so the plotter and the comparison tool
+/// can read them like any benchmark results
+pub fn write_results<P: AsRef<Path>>(dir: P, results: &[SimResult]) -> Result<(), Box<dyn Error>> {
+    for result in results {
+        let path = dir
+            .as_ref()
+            .join(&result.id.group)
+            .join(&result.bench_name)
+            .join("new")
+            .join("estimates.json");
+        result.estimates.write(path)?;
+    }
+    Ok(())
+}
+
+// Step costs are interpolated between the measured numbers of sessions
+#[test]
+fn test_step_costs() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/ec2_data/piecewise_bench");
+    let costs = StepCosts::load(dir, Scheme::Abe.group_name()).unwrap();
+
+    let (t1, t2, t32) = (costs.cost(1, 1), costs.cost(1, 2), costs.cost(1, 32));
+    assert!(t1 > 0.0 && t1 < 1e-3);
+    assert_eq!(costs.cost(1, 0), t1);
+    assert!((costs.cost(1, 3) - (t2 + costs.cost(1, 4)) / 2.0).abs() < 1e-12);
+    assert!((costs.cost(1, 64) - 2.0 * t32).abs() < 1e-12);
+
+    assert!(StepCosts::load(dir, Scheme::BlindRsa.group_name()).is_err());
+}
+
+// With no crypto and no network, the runtime comes down to the server's sleeps and the arrivals
+// The confidence interval on the mean stays above 0, however spread out the runs are
+#[test]
+fn test_estimates_of() {
+    let estimates = estimates_of(&[1.0, 100.0]);
+    assert_eq!(estimates.mean, 50.5e9);
+    assert_eq!(estimates.mean_lower, 1e9);
+    assert!(estimates.mean_upper > estimates.mean);
+}
+
+#[test]
+fn test_simulate_run() {
+    let costs = StepCosts::constant([0.0; 4]);
+    let network = Network::instant();
+    let params = |pool_size, max_parallel_sessions, eiat_ms| SimParams {
+        costs: &costs,
+        pool_size,
+        max_parallel_sessions,
+        num_clients: 5,
+        arrival_process: ArrivalProcess::Constant,
+        mean_interarrival: Duration::from_secs_f64(eiat_ms / 1000.0),
+        server_latency: Normal::new(10.0, 0.0).unwrap(),
+        network: &network,
+        retry_policy: RetryPolicy::default(),
+        server_backend: ServerBackend::ThreadPool,
+    };
+    let run = |params: &SimParams| simulate_run(params, &mut SimRngs::new(Some(1), "test"));
+
+    // Clients arrive every 100ms, and each takes 20ms, so the run is as long as the arrivals
+    let outcome = run(&params(1, usize::MAX, 100.0));
+    assert!((outcome.runtime - 0.5).abs() < 1e-9);
+
+    // Clients arrive every 1ms, so the one thread is never idle for all 10 of its 10ms sleeps
+    let outcome = run(&params(1, usize::MAX, 1.0));
+    assert!((outcome.runtime - 0.1).abs() < 1e-9);
+    assert_eq!(outcome.num_retries, 0);
+
+    // Unless it's async, in which case the sleeps overlap and the last client is done 20ms after
+    // it arrives at 4ms
+    let outcome = run(&SimParams {
+        server_backend: ServerBackend::Async,
+        ..params(1, usize::MAX, 1.0)
+    });
+    assert!((outcome.runtime - 0.024).abs() < 1e-9);
+
+    // With only one session at a time, clients get turned away and have to come back later
+    let outcome = run(&params(4, 1, 1.0));
+    assert!(outcome.num_retries > 0);
+    assert_eq!(outcome.num_failed, 0);
+    assert!(outcome.runtime > 0.1);
+}
+
+// The simulation lands close to what the EC2 benchmarks measured
+#[test]
+fn test_validate_against_ec2() {
+    use crate::compare::compare_dirs;
+
+    let data = concat!(env!("CARGO_MANIFEST_DIR"), "/ec2_data");
+    let scenario = Scenario {
+        seed: Some(1),
+        ..Scenario::default()
+    };
+    let (results, skipped) =
+        simulate_scenario(&scenario, format!("{}/piecewise_bench", data), 20).unwrap();
+    assert_eq!(
+        skipped,
+        [
+            Scheme::ClauseBlindSchnorr,
+            Scheme::BlindRsa,
+            Scheme::BlindBls
+        ]
+    );
+    assert!(results.iter().all(|r| r.num_failed == 0));
+
+    let dir = std::env::temp_dir().join(format!("blind_sig_bench_sim_{}", std::process::id()));
+    write_results(&dir, &results).unwrap();
+    let set = compare_dirs(format!("{}/webserver_bench", data), &dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(set.comparisons.len(), results.len());
+    for c in &set.comparisons {
+        // Abe's are within a few percent. Sequential Schnorr's depend more on how the retries
+        // line up, and are within about 15%, as the README says.
+        let tolerance = if c.id.group == Scheme::Abe.group_name() {
+            0.1
+        } else {
+            0.15
+        };
+        assert!(
+            c.relative_change().abs() < tolerance,
+            "{:?} is off by {:+.1}%",
+            c.id,
+            100.0 * c.relative_change()
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{
+    common::BlindSigProtocol, error::Error, session::SessionStore, webserver::SessionHandler,
+};
+use std::sync::{mpsc, Arc, Mutex};
+
+use rand::distributions::Distribution;
+
+/// A message between client and server, along with the session it belongs to
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    /// The session token. The client leaves this out of the first message of a session. The
+    /// server includes a fresh one in every response that the client has to answer, and each one
+    /// is good for exactly one message.
+    pub token: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// A way for a client to reach a server. Message bodies are opaque byte strings, so the session
+/// logic on either end is the same no matter how they're carried.
+pub trait Transport: Send + 'static {
+    /// Sends a message to the server and waits for its response. Returns
+    /// `Error::CapacityExceeded` if the server can't take the message right now.
+    fn round_trip(&self, req: &Envelope) -> Result<Envelope, Error>;
+}
+
+/// The HTTP header that carries the session token, in both directions
+pub(crate) const SESSION_TOKEN_HEADER: &str = "session_token";
+
+/// Talks to a server started with `webserver::start_server`
+#[derive(Clone)]
+pub struct HttpTransport {
+    url: String,
+}
+
+impl HttpTransport {
+    pub fn new(addr: &str) -> HttpTransport {
+        HttpTransport {
+            url: format!("http://{}/sign", addr),
+        }
+    }
+}
+
+impl Transport for HttpTransport {
+    fn round_trip(&self, req: &Envelope) -> Result<Envelope, Error> {
+        use reqwest::{blocking::Client, header::CONTENT_TYPE, StatusCode};
+
+        let mut builder = Client::new()
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(req.body.clone());
+        if let Some(token) = &req.token {
+            builder = builder.header(SESSION_TOKEN_HEADER, token);
+        }
+        let res = builder.send().map_err(|e| {
+            if e.is_connect() {
+                Error::ConnectionFailed(e.to_string())
+            } else {
+                Error::Transport(e.to_string())
+            }
+        })?;
+
+        let status = res.status();
+        if status == StatusCode::CONFLICT {
+            Err(Error::CapacityExceeded)
+        } else if !status.is_success() {
+            let body = res.text().unwrap_or_default();
+            Err(Error::Transport(format!(
+                "server said {}: {}",
+                status, body
+            )))
+        } else {
+            let token = match res.headers().get(SESSION_TOKEN_HEADER) {
+                Some(token) => Some(
+                    token
+                        .to_str()
+                        .map_err(|e| Error::InvalidEncoding(e.to_string()))?
+                        .to_string(),
+                ),
+                None => None,
+            };
+            let body = res
+                .bytes()
+                .map_err(|e| Error::Transport(e.to_string()))?
+                .to_vec();
+            Ok(Envelope { token, body })
+        }
+    }
+}
+
+/// A client's message to an in-process server, along with where to send the response
+struct ChannelRequest {
+    envelope: Envelope,
+    reply: mpsc::Sender<Result<Envelope, Error>>,
+}
+
+/// Talks to a server started with `start_channel_server`. Nothing goes through the network stack,
+/// so the only latency is the one the server simulates.
+#[derive(Clone)]
+pub struct ChannelTransport {
+    sender: mpsc::Sender<ChannelRequest>,
+}
+
+impl Transport for ChannelTransport {
+    fn round_trip(&self, req: &Envelope) -> Result<Envelope, Error> {
+        let (reply, response) = mpsc::channel();
+        let req = ChannelRequest {
+            envelope: req.clone(),
+            reply,
+        };
+
+        self.sender
+            .send(req)
+            .map_err(|_| Error::Transport("server has shut down".to_string()))?;
+        response
+            .recv()
+            .map_err(|_| Error::Transport("server dropped the request".to_string()))?
+    }
+}
+
+/// Starts an in-process server with `pool_size` worker threads. Returns the server's keypair and a
+/// transport for reaching it. The server shuts down once every copy of the transport is dropped.
+pub fn start_channel_server<S, D>(
+    pool_size: usize,
+    global_state: Arc<SessionStore<S::ServerSession>>,
+    latency_distr: D,
+) -> (S::Privkey, S::Pubkey, ChannelTransport)
+where
+    S: BlindSigProtocol + 'static,
+    D: Distribution<f64> + Send + Sync + 'static,
+{
+    let handler = Arc::new(SessionHandler::<S, D>::new(
+        pool_size,
+        global_state,
+        latency_distr,
+    ));
+    let (privkey, pubkey) = handler.keypair();
+
+    let (sender, receiver) = mpsc::channel::<ChannelRequest>();
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    for _ in 0..pool_size {
+        let handler = handler.clone();
+        let receiver = receiver.clone();
+        std::thread::spawn(move || loop {
+            // Only hold the lock long enough to take the next message. If every sender is gone,
+            // there's nothing left to do.
+            let req = match receiver.lock().unwrap().recv() {
+                Ok(req) => req,
+                Err(_) => break,
+            };
+
+            let resp = handler.handle(&req.envelope);
+            // If the client stopped waiting, there's nobody to tell
+            let _ = req.reply.send(resp);
+        });
+    }
+
+    (privkey, pubkey, ChannelTransport { sender })
+}
+
+// A sequential scheme should turn away a second client while the first one's session is open, and
+// should take it once that session is done. Tokens can't be reused.
+#[test]
+fn test_channel_capacity() {
+    use crate::{
+        common::{ClientStep, FourMoveClientMsg},
+        schnorr::BlindSchnorr,
+        session::AdmissionPolicy,
+    };
+    type S = BlindSchnorr;
+
+    let mut csprng = rand::thread_rng();
+    let m = b"Hello world";
+    let latency_distr = rand_distr::Normal::new(0f64, 1f64).unwrap();
+    let timeout = std::time::Duration::from_secs(10);
+    let (_privkey, pubkey, transport) = start_channel_server::<S, _>(
+        2,
+        Arc::new(SessionStore::new(timeout, AdmissionPolicy::Reject)),
+        latency_distr,
+    );
+    let start = Envelope {
+        token: None,
+        body: serde_json::to_vec(&FourMoveClientMsg::<S>::Start).unwrap(),
+    };
+
+    // Client a starts a session, which takes up the only slot
+    let (session, _) = S::client_start(&mut csprng, &pubkey, m);
+    let resp = transport.round_trip(&start).unwrap();
+    assert_eq!(
+        transport.round_trip(&start).unwrap_err(),
+        Error::CapacityExceeded
+    );
+
+    // Client a finishes its session, which frees up the slot
+    let server_msg = serde_json::from_slice(&resp.body).unwrap();
+    let client_msg = match S::client_step(&mut csprng, &pubkey, m, session, &server_msg) {
+        Ok(ClientStep::Continue(_, msg)) => msg,
+        _ => panic!("client finished early"),
+    };
+    let req = Envelope {
+        token: resp.token,
+        body: serde_json::to_vec(&client_msg).unwrap(),
+    };
+    let resp = transport.round_trip(&req).unwrap();
+    assert!(resp.token.is_none());
+    assert!(transport.round_trip(&start).is_ok());
+
+    // The token was used up, so it can't be replayed
+    assert_eq!(
+        transport.round_trip(&req).unwrap_err(),
+        Error::UnknownSession
+    );
+}
@@ -1,161 +1,304 @@
-use crate::common::FourMoveBlindSig;
+use crate::{
+    async_server::start_async_server,
+    common::{BlindSigProtocol, ClientStep, ServerStep},
+    error::Error,
+    retry::{ClientStats, RetryPolicy},
+    scenario::ServerBackend,
+    session::{start_reaper, SessionStore},
+    transport::{Envelope, Transport, SESSION_TOKEN_HEADER},
+};
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering::SeqCst},
         Arc,
     },
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use dashmap::DashMap;
-use rand::{distributions::Distribution, Rng};
-
-// If a client gets an HTTP 409 from the server, it waits this many milliseconds before
-// reconnecting
-const CLIENT_BACKOFF_TIME: u64 = 75;
+use rand::distributions::Distribution;
 
 type ServerFunc = Box<dyn Fn(&rouille::Request) -> rouille::Response + Send + Sync + 'static>;
-pub type ClientFunc = Box<dyn Fn() + Send>;
+pub type ClientFunc = Box<dyn Fn() -> Result<ClientStats, Error> + Send>;
+
+/// Returns the HTTP status code that the server responds with when it hits the given error
+pub(crate) fn status_code(err: &Error) -> u16 {
+    match err {
+        Error::InvalidEncoding(_) | Error::UnexpectedMessage => 400,
+        Error::UnknownSession => 404,
+        Error::CapacityExceeded => 409,
+        // The client can't make the server's own check fail, so this is the server's fault
+        Error::VerificationFailed => 500,
+        Error::ConnectionFailed(_) | Error::Transport(_) => 502,
+    }
+}
 
-fn make_server_func<S, D>(
-    global_state: Arc<DashMap<String, S::ServerState>>,
+fn error_response(err: &Error) -> rouille::Response {
+    rouille::Response::text(err.to_string()).with_status_code(status_code(err))
+}
+
+/// The transport-independent half of the server. This holds the keypair and every open session,
+/// and turns an encoded message from a client into an encoded response.
+pub(crate) struct SessionHandler<S: BlindSigProtocol, D> {
+    privkey: S::Privkey,
+    pubkey: S::Pubkey,
+    global_state: Arc<SessionStore<S::ServerSession>>,
     latency_distr: D,
-) -> (S::Privkey, S::Pubkey, ServerFunc)
+}
+
+impl<S, D> SessionHandler<S, D>
 where
-    S: FourMoveBlindSig,
-    D: Distribution<f64> + Send + Sync + 'static,
+    S: BlindSigProtocol,
+    D: Distribution<f64>,
 {
-    use rouille::{input::json_input, try_or_400, Request, Response};
-
-    let mut csprng = rand::thread_rng();
-    let (privkey, pubkey) = S::keygen(&mut csprng);
-    let (privkey_copy, pubkey_copy) = (privkey.clone(), pubkey.clone());
+    /// Makes a handler with a fresh keypair for a server with `pool_size` threads, and starts
+    /// reaping abandoned sessions. Reaping at half the timeout means no session outlives it by more
+    /// than half.
+    pub(crate) fn new(
+        pool_size: usize,
+        global_state: Arc<SessionStore<S::ServerSession>>,
+        latency_distr: D,
+    ) -> SessionHandler<S, D> {
+        let (privkey, pubkey) = S::keygen(&mut rand::thread_rng());
+        start_reaper(&global_state, global_state.timeout() / 2);
+        // A request waiting for a slot holds on to its thread. If they all did, none would be left
+        // for the sessions holding the slots, and nobody would get anywhere until the timeout.
+        global_state.limit_waiting(pool_size.saturating_sub(1));
+
+        SessionHandler {
+            privkey,
+            pubkey,
+            global_state,
+            latency_distr,
+        }
+    }
 
-    let handler = move |req: &Request| {
-        let mut csprng = rand::thread_rng();
+    pub(crate) fn keypair(&self) -> (S::Privkey, S::Pubkey) {
+        (self.privkey.clone(), self.pubkey.clone())
+    }
 
-        let client_id = req
-            .header("client_id")
-            .expect("no client_id provided")
-            .to_string();
-
-        // Only do as many parallels sessions as is permitted. If the global session is empty or
-        // the given client ID matches, we can continue. Otherwise 400.
-        // I know this is actually a race condition, and you might get more parallelism than you
-        // intended, but:
-        // 1. this is unlikely to happen,
-        // 2. even if it does, it will not cascade into a big parallel mess, and
-        // 3. this is just a benchmark so chill.
-        if !(global_state.len() < S::MAX_PARALLEL_SESSIONS
-            || global_state.get(&client_id).is_some())
-        {
-            return Response::text("").with_status_code(409);
+    /// Processes a message from a client, and returns the response. Message bodies are
+    /// JSON-encoded.
+    pub(crate) fn handle(&self, req: &Envelope) -> Result<Envelope, Error> {
+        let (res, pause_time) = self.process(req);
+        // Simulate latency by pausing. This ties up the server thread. A network model on the
+        // client's transport doesn't.
+        if !pause_time.is_zero() {
+            sleep(pause_time);
         }
+        res
+    }
+
+    /// Decodes a message and finds its session, starting a new one if it's the first message
+    fn open(&self, req: &Envelope) -> Result<(Option<S::ServerSession>, S::ClientMsg), Error> {
+        let global_state = &self.global_state;
+
+        let client_msg: S::ClientMsg =
+            serde_json::from_slice(&req.body).map_err(|e| Error::InvalidEncoding(e.to_string()))?;
+
+        let server_session = match &req.token {
+            // Pick up where this client left off. Tokens are single-use, so this fails if the token
+            // was never issued, was already used, or belongs to an abandoned session.
+            Some(token) => Some(global_state.take(token).ok_or(Error::UnknownSession)?),
+            // Anything but a first message without a token belongs to a session the server doesn't
+            // know about. That's the answer whether or not the server's full.
+            None if !S::opens_session(&client_msg) => return Err(Error::UnknownSession),
+            // This is a new session, so it has to get one of the scheme's slots before it can
+            // start. Depending on the admission policy, this either waits for a slot or fails with
+            // a 409.
+            None => {
+                global_state.admit(S::MAX_PARALLEL_SESSIONS)?;
+                None
+            }
+        };
+
+        Ok((server_session, client_msg))
+    }
 
-        let res = match req.url().as_ref() {
-            "/sign1" => {
-                let (server_state, server_resp1) = S::sign1(&mut csprng, &pubkey);
+    /// Does everything `handle` does except pause. Returns the response, and how long to pause
+    /// before sending it. Messages that are turned away before any crypto is done get no pause.
+    pub(crate) fn process(&self, req: &Envelope) -> (Result<Envelope, Error>, Duration) {
+        let mut csprng = rand::thread_rng();
+        let global_state = &self.global_state;
 
-                global_state.insert(client_id, server_state);
-                Response::json(&server_resp1)
+        let (server_session, client_msg) = match self.open(req) {
+            Ok(opened) => opened,
+            Err(e) => return (Err(e), Duration::default()),
+        };
+        let step = S::server_step(
+            &mut csprng,
+            &self.privkey,
+            &self.pubkey,
+            server_session,
+            &client_msg,
+        );
+
+        let encode = |server_msg: &S::ServerMsg| {
+            serde_json::to_vec(server_msg).map_err(|e| Error::InvalidEncoding(e.to_string()))
+        };
+        let res = match step {
+            // Store the session under a fresh token, which the client needs for its next message
+            Ok(ServerStep::Continue(server_session, server_msg)) => {
+                let token = global_state.insert(server_session);
+                encode(&server_msg).map(|body| Envelope {
+                    token: Some(token),
+                    body,
+                })
             }
-            "/sign2" => {
-                let server_state = global_state
-                    .get(&client_id)
-                    .expect("missing server state for this client_id");
-                let client_resp: S::ClientResp = try_or_400!(json_input(req));
-                let server_resp2 = S::sign2(&privkey, &server_state, &client_resp);
-
-                drop(server_state);
-                global_state
-                    .remove(&client_id)
-                    .expect("couldn't remove from global state");
-
-                Response::json(&server_resp2)
+            // Either way the session is over, so it gives up its slot
+            Ok(ServerStep::Done(server_msg)) => {
+                global_state.release();
+                encode(&server_msg).map(|body| Envelope { token: None, body })
+            }
+            Err(e) => {
+                global_state.release();
+                Err(e)
             }
-            other => panic!("unexpected url {}", other),
         };
 
-        // Simulate latency by sampling from the latency distribution and pausing for that time
-        let pause_time = std::cmp::max(0, latency_distr.sample(&mut csprng) as i64);
-        sleep(Duration::from_millis(pause_time as u64));
+        // The latency is sampled from the latency distribution, in whole milliseconds
+        let pause_time = std::cmp::max(0, self.latency_distr.sample(&mut csprng) as i64);
+        (res, Duration::from_millis(pause_time as u64))
+    }
+}
 
-        res
+fn make_server_func<S, D>(
+    pool_size: usize,
+    global_state: Arc<SessionStore<S::ServerSession>>,
+    latency_distr: D,
+) -> (S::Privkey, S::Pubkey, ServerFunc)
+where
+    S: BlindSigProtocol + 'static,
+    D: Distribution<f64> + Send + Sync + 'static,
+{
+    use rouille::{Request, Response};
+    use std::io::Read;
+
+    let session_handler = SessionHandler::<S, D>::new(pool_size, global_state, latency_distr);
+    let (privkey, pubkey) = session_handler.keypair();
+
+    let handler = move |req: &Request| {
+        if req.url() != "/sign" {
+            return Response::empty_404();
+        }
+
+        let mut body = Vec::new();
+        if let Some(mut data) = req.data() {
+            if let Err(e) = data.read_to_end(&mut body) {
+                return error_response(&Error::Transport(e.to_string()));
+            }
+        }
+
+        let req = Envelope {
+            token: req.header(SESSION_TOKEN_HEADER).map(str::to_string),
+            body,
+        };
+        match session_handler.handle(&req) {
+            Ok(Envelope { token, body }) => {
+                let res = Response::from_data("application/json", body);
+                match token {
+                    Some(token) => res.with_additional_header(SESSION_TOKEN_HEADER, token),
+                    None => res,
+                }
+            }
+            Err(e) => error_response(&e),
+        }
     };
 
-    (privkey_copy, pubkey_copy, Box::new(handler))
+    (privkey, pubkey, Box::new(handler))
 }
 
-pub fn make_client<S: FourMoveBlindSig>(addr: &'static str, pubkey: S::Pubkey) -> ClientFunc {
-    use reqwest::blocking::Client;
-
+/// Makes a client that runs a single signing session over the given transport, and checks the
+/// resulting signature. Messages that the server turns away, or that can't reach it, are retried
+/// according to the given policy.
+pub fn make_client<S, T>(transport: T, pubkey: S::Pubkey, retry_policy: RetryPolicy) -> ClientFunc
+where
+    S: BlindSigProtocol,
+    T: Transport,
+{
     let client = move || {
         let mut csprng = rand::thread_rng();
         let m = b"Hello world";
-        let client_id: String = std::iter::repeat(())
-            .map(|()| csprng.sample(rand::distributions::Alphanumeric))
-            .take(7)
-            .collect();
-
-        // Do step 1. Loop until the request is accepted
-        let server_resp1: S::ServerResp1 = loop {
-            let res = Client::new()
-                .get(&format!("http://{}/sign1", addr))
-                .header("client_id", &client_id)
-                .send()
-                .expect("didn't get sign1 response");
-            if res.status() == reqwest::StatusCode::from_u16(409).unwrap() {
-                // Server's busy. Back off for some time before trying again
-                sleep(Duration::from_millis(CLIENT_BACKOFF_TIME));
-                continue;
-            } else {
-                let resp = res.json().expect("invalid ServerResp1");
-                break resp;
-            }
-        };
-
-        // Do step 2. Loop until the request is accepted
-        let (client_state, client_resp) = S::user1(&mut csprng, &pubkey, m, &server_resp1);
-        let server_resp2: S::ServerResp2 = loop {
-            let res = Client::new()
-                .get(&format!("http://{}/sign2", addr))
-                .header("client_id", &client_id)
-                .json(&client_resp)
-                .send()
-                .expect("didn't get sign2 response");
-            if res.status() == reqwest::StatusCode::from_u16(409).unwrap() {
-                // Server's busy. Back off for some time before trying again
-                sleep(Duration::from_millis(CLIENT_BACKOFF_TIME));
-                continue;
-            } else {
-                let resp = res.json().expect("invalid ServerResp2");
-                break resp;
+        let start_time = Instant::now();
+        let deadline = retry_policy.deadline.map(|d| start_time + d);
+        let mut stats = ClientStats::default();
+        // The server hands out a token for the session once it's started
+        let mut token = None;
+
+        // Send messages back and forth until we have a signature
+        let (mut client_session, mut client_msg) = S::client_start(&mut csprng, &pubkey, m);
+        let sig = loop {
+            let req = Envelope {
+                token: token.take(),
+                body: serde_json::to_vec(&client_msg)
+                    .map_err(|e| Error::InvalidEncoding(e.to_string()))?,
+            };
+
+            // Send the next message. Retry until the request is accepted or we run out of
+            // patience.
+            let mut attempt = 1;
+            let server_msg: S::ServerMsg = loop {
+                match transport.round_trip(&req) {
+                    Err(e) if RetryPolicy::is_retryable(&e) => {
+                        let backoff = retry_policy.backoff(&mut csprng, attempt - 1);
+                        let out_of_time = deadline.is_some_and(|d| Instant::now() + backoff > d);
+                        if attempt >= retry_policy.max_attempts || out_of_time {
+                            return Err(e);
+                        }
+
+                        // Back off for some time before trying again
+                        sleep(backoff);
+                        attempt += 1;
+                        stats.num_retries += 1;
+                        stats.backoff_time += backoff;
+                    }
+                    Err(e) => return Err(e),
+                    Ok(resp) => {
+                        token = resp.token;
+                        break serde_json::from_slice(&resp.body)
+                            .map_err(|e| Error::InvalidEncoding(e.to_string()))?;
+                    }
+                }
+            };
+
+            let step = S::client_step(&mut csprng, &pubkey, m, client_session, &server_msg)?;
+            match step {
+                ClientStep::Continue(session, msg) => {
+                    client_session = session;
+                    client_msg = msg;
+                }
+                ClientStep::Done(sig) => break sig,
             }
         };
-        let sig = S::user2(&pubkey, &client_state, m, &server_resp2).unwrap();
 
-        assert!(S::verify(&pubkey, m, &sig));
+        if S::verify(&pubkey, m, &sig) {
+            stats.total_time = start_time.elapsed();
+            Ok(stats)
+        } else {
+            Err(Error::VerificationFailed)
+        }
     };
 
     Box::new(client)
 }
 
 pub fn start_server<S, D>(
-    addr: &'static str,
+    addr: &str,
     pool_size: usize,
-    global_state: Arc<DashMap<String, S::ServerState>>,
+    global_state: Arc<SessionStore<S::ServerSession>>,
     latency_distr: D,
 ) -> (S::Privkey, S::Pubkey, Arc<AtomicBool>)
 where
-    S: FourMoveBlindSig,
+    S: BlindSigProtocol + 'static,
     D: Distribution<f64> + Send + Sync + 'static,
 {
-    let (privkey, pubkey, server_func) = make_server_func::<S, _>(global_state, latency_distr);
+    let (privkey, pubkey, server_func) =
+        make_server_func::<S, _>(pool_size, global_state, latency_distr);
 
     let stop_var = Arc::new(AtomicBool::new(false));
     let stop_var_copy = stop_var.clone();
 
+    let addr = addr.to_string();
     std::thread::spawn(move || {
         let server = rouille::Server::new(addr, server_func)
             .expect("couldn't make server")
@@ -169,15 +312,93 @@ where
     (privkey, pubkey, stop_var_copy)
 }
 
+/// Starts an HTTP server on the given backend. Either way, `pool_size` threads do the crypto.
+pub fn start_backend<S, D>(
+    backend: ServerBackend,
+    addr: &str,
+    pool_size: usize,
+    global_state: Arc<SessionStore<S::ServerSession>>,
+    latency_distr: D,
+) -> (S::Privkey, S::Pubkey, Arc<AtomicBool>)
+where
+    S: BlindSigProtocol + 'static,
+    D: Distribution<f64> + Send + Sync + 'static,
+{
+    match backend {
+        ServerBackend::ThreadPool => {
+            start_server::<S, _>(addr, pool_size, global_state, latency_distr)
+        }
+        ServerBackend::Async => {
+            start_async_server::<S, _>(addr, pool_size, global_state, latency_distr)
+        }
+    }
+}
+
+// None of the clients in these tests should stall for anywhere near this long
 #[cfg(test)]
-fn test_webserver<S: FourMoveBlindSig>() {
-    let server_addr = "localhost:23489";
+const SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Runs 10 clients against an in-process server
+#[cfg(test)]
+fn test_protocol<S: BlindSigProtocol + 'static>() {
+    use crate::{session::AdmissionPolicy, transport::start_channel_server};
+
     // Make a global server state for all the cores to run with
-    let my_global_state: Arc<DashMap<String, <S as FourMoveBlindSig>::ServerState>> =
-        Arc::new(DashMap::new());
+    let my_global_state: Arc<SessionStore<<S as BlindSigProtocol>::ServerSession>> =
+        Arc::new(SessionStore::new(SESSION_TIMEOUT, AdmissionPolicy::Reject));
 
-    // Make an arbitrary latency ditribution (this one is μ = 50ms, σ = 10ms), and start the server
+    // Make an arbitrary latency ditribution (this one is μ = 5ms, σ = 1ms), and start the server
     // with that latency distribution and 1 thread in the threadpool
+    let latency_distr = rand_distr::Normal::new(5f64, 1f64).unwrap();
+    let (_privkey, pubkey, transport) =
+        start_channel_server::<S, _>(1, my_global_state, latency_distr);
+
+    // Make 10 clients connect to the server
+    let mut threads = Vec::new();
+    for _ in 0..10 {
+        let client = make_client::<S, _>(transport.clone(), pubkey.clone(), RetryPolicy::default());
+        threads.push(std::thread::spawn(client));
+    }
+
+    // Wait for all the clients finish
+    for thread in threads.into_iter() {
+        thread.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+fn test_blind_schnorr() {
+    test_protocol::<crate::schnorr::BlindSchnorr>();
+}
+
+#[test]
+fn test_clause_blind_schnorr() {
+    test_protocol::<crate::clause_schnorr::ClauseBlindSchnorr>();
+}
+
+#[test]
+fn test_blind_bls() {
+    test_protocol::<crate::blind_bls::BlindBls>();
+}
+
+#[test]
+fn test_blind_rsa() {
+    test_protocol::<crate::blind_rsa::BlindRsaRandomized>();
+}
+
+#[test]
+fn test_abe() {
+    test_protocol::<crate::abe::Abe>();
+}
+
+// The same thing, but over HTTP
+#[test]
+fn test_http() {
+    use crate::{session::AdmissionPolicy, transport::HttpTransport};
+    type S = crate::schnorr::BlindSchnorr;
+
+    let server_addr = "localhost:23489";
+    let my_global_state = Arc::new(SessionStore::new(SESSION_TIMEOUT, AdmissionPolicy::Reject));
     let latency_distr = rand_distr::Normal::new(50f64, 10f64).unwrap();
     let (_privkey, pubkey, stop_var) =
         start_server::<S, _>(server_addr, 1, my_global_state, latency_distr);
@@ -185,28 +406,327 @@ fn test_webserver<S: FourMoveBlindSig>() {
     // Let the server start up for a second
     sleep(Duration::from_secs(1));
 
-    // Make 10 clients connect to the server
     let mut threads = Vec::new();
     for _ in 0..10 {
-        let client = make_client::<S>(server_addr, pubkey.clone());
+        let client = make_client::<S, _>(
+            HttpTransport::new(server_addr),
+            pubkey,
+            RetryPolicy::default(),
+        );
         threads.push(std::thread::spawn(client));
     }
-
-    // Wait for all the clients finish
     for thread in threads.into_iter() {
-        thread.join().unwrap();
+        thread.join().unwrap().unwrap();
     }
 
-    // Kill the server
+    // Kill the server, and wait a second for it to get the message and free up the port
     stop_var.store(true, SeqCst);
+    sleep(Duration::from_secs(1));
 }
 
+// Malformed requests should get error statuses, and shouldn't take down the server
 #[test]
-fn test_blind_schnorr() {
-    test_webserver::<crate::schnorr::BlindSchnorr>();
+fn test_malformed_requests() {
+    use crate::{
+        common::{FourMoveBlindSig, FourMoveClientMsg},
+        session::AdmissionPolicy,
+        transport::HttpTransport,
+    };
+    use reqwest::blocking::Client;
+    type S = crate::abe::Abe;
+
+    let server_addr = "localhost:23490";
+    let my_global_state = Arc::new(SessionStore::new(SESSION_TIMEOUT, AdmissionPolicy::Reject));
+    let latency_distr = rand_distr::Normal::new(0f64, 1f64).unwrap();
+    let (_privkey, pubkey, stop_var) =
+        start_server::<S, _>(server_addr, 1, my_global_state, latency_distr);
+    sleep(Duration::from_secs(1));
+
+    let url = format!("http://{}/sign", server_addr);
+    let status_of = |req: reqwest::blocking::RequestBuilder| req.send().unwrap().status().as_u16();
+
+    // Not JSON
+    assert_eq!(status_of(Client::new().post(&url).body("hello")), 400);
+    // Unknown URL
+    assert_eq!(
+        status_of(Client::new().post(&format!("http://{}/sign3", server_addr))),
+        404
+    );
+    // A challenge for a session that was never started
+    let mut csprng = rand::thread_rng();
+    let (_, server_resp1) = <S as FourMoveBlindSig>::sign1(&mut csprng, &pubkey);
+    let (_, client_resp) =
+        <S as FourMoveBlindSig>::user1(&mut csprng, &pubkey, b"Hello world", &server_resp1);
+    let client_msg = FourMoveClientMsg::<S>::ClientResp(client_resp);
+    assert_eq!(status_of(Client::new().post(&url).json(&client_msg)), 404);
+    // A made-up session token
+    assert_eq!(
+        status_of(
+            Client::new()
+                .post(&url)
+                .header(SESSION_TOKEN_HEADER, "0123456789abcdef0123456789abcdef")
+                .json(&client_msg)
+        ),
+        404
+    );
+    // A replayed session token. The first use goes through, and the second doesn't.
+    let res = Client::new()
+        .post(&url)
+        .json(&FourMoveClientMsg::<S>::Start)
+        .send()
+        .unwrap();
+    let token = res.headers()[SESSION_TOKEN_HEADER].to_str().unwrap();
+    let send_challenge = || {
+        status_of(
+            Client::new()
+                .post(&url)
+                .header(SESSION_TOKEN_HEADER, token)
+                .json(&client_msg),
+        )
+    };
+    assert_eq!(send_challenge(), 200);
+    assert_eq!(send_challenge(), 404);
+
+    // The server should still work
+    make_client::<S, _>(
+        HttpTransport::new(server_addr),
+        pubkey,
+        RetryPolicy::default(),
+    )()
+    .unwrap();
+
+    stop_var.store(true, SeqCst);
+    sleep(Duration::from_secs(1));
 }
 
+// A client that walks away mid-session shouldn't wedge a sequential server forever
 #[test]
-fn test_abe() {
-    test_webserver::<crate::abe::Abe>();
+fn test_abandoned_session() {
+    use crate::{
+        common::FourMoveClientMsg, session::AdmissionPolicy, transport::start_channel_server,
+    };
+    type S = crate::schnorr::BlindSchnorr;
+
+    let my_global_state = Arc::new(SessionStore::new(
+        Duration::from_millis(200),
+        AdmissionPolicy::Reject,
+    ));
+    let latency_distr = rand_distr::Normal::new(0f64, 1f64).unwrap();
+    let (_privkey, pubkey, transport) =
+        start_channel_server::<S, _>(1, my_global_state.clone(), latency_distr);
+
+    // Start a session and never finish it. That takes up the only slot.
+    let start = Envelope {
+        token: None,
+        body: serde_json::to_vec(&FourMoveClientMsg::<S>::Start).unwrap(),
+    };
+    transport.round_trip(&start).unwrap();
+    assert_eq!(
+        transport.round_trip(&start).unwrap_err(),
+        Error::CapacityExceeded
+    );
+
+    // Once the session is reaped, an honest client can get through
+    sleep(Duration::from_millis(500));
+    assert_eq!(my_global_state.num_evicted(), 1);
+    make_client::<S, _>(transport, pubkey, RetryPolicy::default())().unwrap();
+}
+
+// With a queue, clients of a sequential scheme take turns instead of getting turned away, and there
+// are never two sessions at once
+#[test]
+fn test_queued_admission() {
+    use crate::{session::AdmissionPolicy, transport::start_channel_server};
+    type S = crate::schnorr::BlindSchnorr;
+
+    // Waiting requests tie up server threads, so give the server plenty
+    let my_global_state = Arc::new(SessionStore::new(SESSION_TIMEOUT, AdmissionPolicy::Queue));
+    let latency_distr = rand_distr::Normal::new(5f64, 1f64).unwrap();
+    let (_privkey, pubkey, transport) =
+        start_channel_server::<S, _>(11, my_global_state.clone(), latency_distr);
+
+    let threads: Vec<_> = (0..10)
+        .map(|_| {
+            std::thread::spawn(make_client::<S, _>(
+                transport.clone(),
+                pubkey,
+                RetryPolicy::default(),
+            ))
+        })
+        .collect();
+    for _ in 0..20 {
+        assert!(my_global_state.num_admitted() <= 1);
+        sleep(Duration::from_millis(10));
+    }
+    for thread in threads.into_iter() {
+        thread.join().unwrap().unwrap();
+    }
+
+    assert_eq!(my_global_state.num_rejected(), 0);
+    assert_eq!(my_global_state.num_admitted(), 0);
+}
+
+// With a single server thread, a queued request would leave nothing to serve the session it's
+// waiting on. It gets turned away instead, and its client retries.
+#[test]
+fn test_queued_admission_one_thread() {
+    use crate::{session::AdmissionPolicy, transport::start_channel_server};
+    type S = crate::schnorr::BlindSchnorr;
+
+    let my_global_state = Arc::new(SessionStore::new(SESSION_TIMEOUT, AdmissionPolicy::Queue));
+    let latency_distr = rand_distr::Normal::new(5f64, 1f64).unwrap();
+    let (_privkey, pubkey, transport) =
+        start_channel_server::<S, _>(1, my_global_state.clone(), latency_distr);
+
+    let start = Instant::now();
+    let threads: Vec<_> = (0..3)
+        .map(|_| {
+            std::thread::spawn(make_client::<S, _>(
+                transport.clone(),
+                pubkey,
+                RetryPolicy::default(),
+            ))
+        })
+        .collect();
+    for thread in threads.into_iter() {
+        thread.join().unwrap().unwrap();
+    }
+
+    assert!(start.elapsed() < SESSION_TIMEOUT);
+    assert!(my_global_state.num_rejected() > 0);
+}
+
+// A session can't start with anything but its first message, even when the server's full. That's
+// a 404, not a 409, and it doesn't take up a slot or wait for one.
+#[test]
+fn test_tokenless_client_resp() {
+    use crate::{
+        common::{FourMoveBlindSig, FourMoveClientMsg},
+        schnorr::BlindSchnorr,
+        session::AdmissionPolicy,
+        transport::start_channel_server,
+    };
+    type S = BlindSchnorr;
+
+    let my_global_state = Arc::new(SessionStore::new(SESSION_TIMEOUT, AdmissionPolicy::Queue));
+    let latency_distr = rand_distr::Normal::new(0f64, 0f64).unwrap();
+    let (_privkey, pubkey, transport) =
+        start_channel_server::<S, _>(4, my_global_state.clone(), latency_distr);
+
+    // A well-formed response to a commitment the server never made
+    let mut csprng = rand::thread_rng();
+    let (_, server_resp1) = S::sign1(&mut csprng, &pubkey);
+    let (_, client_resp) = S::user1(&mut csprng, &pubkey, b"hello", &server_resp1);
+
+    let envelope = |msg: &FourMoveClientMsg<S>| Envelope {
+        token: None,
+        body: serde_json::to_vec(msg).unwrap(),
+    };
+    let client_resp = envelope(&FourMoveClientMsg::ClientResp(client_resp));
+    assert_eq!(
+        transport.round_trip(&client_resp).unwrap_err(),
+        Error::UnknownSession
+    );
+    assert_eq!(my_global_state.num_admitted(), 0);
+
+    // Still a 404 with the only slot taken, rather than a wait in line
+    transport
+        .round_trip(&envelope(&FourMoveClientMsg::Start))
+        .unwrap();
+    let start = Instant::now();
+    assert_eq!(
+        transport.round_trip(&client_resp).unwrap_err(),
+        Error::UnknownSession
+    );
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(my_global_state.num_admitted(), 1);
+}
+
+// Answering two different challenges with the same nonce r gives away the Blind Schnorr private
+// key, since x = (s₁ - s₂) / (c₁ - c₂). The server should never do that, no matter what the client
+// sends.
+#[test]
+fn test_no_key_extraction() {
+    use crate::{
+        common::{FourMoveClientMsg, FourMoveServerMsg, GroupElem, Scalar},
+        schnorr::ClientResp,
+        session::AdmissionPolicy,
+    };
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+    use reqwest::blocking::Client;
+    type S = crate::schnorr::BlindSchnorr;
+
+    let server_addr = "localhost:23491";
+    let my_global_state = Arc::new(SessionStore::new(SESSION_TIMEOUT, AdmissionPolicy::Reject));
+    let latency_distr = rand_distr::Normal::new(0f64, 1f64).unwrap();
+    let (_privkey, pubkey, stop_var) =
+        start_server::<S, _>(server_addr, 1, my_global_state, latency_distr);
+    sleep(Duration::from_secs(1));
+
+    let url = format!("http://{}/sign", server_addr);
+    let mut csprng = rand::thread_rng();
+    let start_session = || {
+        let res = Client::new()
+            .post(&url)
+            .json(&FourMoveClientMsg::<S>::Start)
+            .send()
+            .unwrap();
+        res.headers()[SESSION_TOKEN_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+    let send_challenge = |token: &str, c: &Scalar| {
+        let client_resp = ClientResp::from_bytes(&c.to_bytes()).unwrap();
+        Client::new()
+            .post(&url)
+            .header(SESSION_TOKEN_HEADER, token)
+            .json(&FourMoveClientMsg::<S>::ClientResp(client_resp))
+            .send()
+            .unwrap()
+    };
+    let response_to = |res: reqwest::blocking::Response| match res.json().unwrap() {
+        FourMoveServerMsg::<S>::ServerResp2(resp) => Scalar::from_bytes(&resp.to_bytes()).unwrap(),
+        _ => panic!("expected a response to the challenge"),
+    };
+    let (c1, c2) = (Scalar::random(&mut csprng), Scalar::random(&mut csprng));
+
+    // The first challenge gets answered, and a second challenge in the same session doesn't
+    let token = start_session();
+    let s1 = response_to(send_challenge(&token, &c1));
+    assert_eq!(send_challenge(&token, &c2).status().as_u16(), 404);
+
+    // A second session gets a fresh nonce, so the two responses don't give away the key
+    let token = start_session();
+    let s2 = response_to(send_challenge(&token, &c2));
+    let x = (s1.0 - s2.0) * (c1.0 - c2.0).invert();
+    let X = GroupElem::from_bytes(&pubkey.to_bytes()).unwrap();
+    assert!(&x * &RISTRETTO_BASEPOINT_TABLE != X.0);
+
+    stop_var.store(true, SeqCst);
+    sleep(Duration::from_secs(1));
+}
+
+// A client retries when it can't reach the server, but only as many times as it's allowed
+#[test]
+fn test_retry_gives_up() {
+    use crate::{retry::RetryPolicy, transport::HttpTransport};
+    type S = crate::schnorr::BlindSchnorr;
+
+    // Nothing is listening on this port
+    let (_, pubkey) = S::keygen(&mut rand::thread_rng());
+    let retry_policy = RetryPolicy {
+        initial_backoff: Duration::from_millis(10),
+        max_attempts: 3,
+        ..RetryPolicy::default()
+    };
+    let client = make_client::<S, _>(HttpTransport::new("localhost:23492"), pubkey, retry_policy);
+
+    let start = Instant::now();
+    match client() {
+        Err(Error::ConnectionFailed(_)) => (),
+        _ => panic!("expected the connection to fail"),
+    }
+    // The client waited twice, for at most 10ms and then 20ms
+    assert!(start.elapsed() < Duration::from_secs(5));
 }